@@ -1,35 +1,183 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::clipboard;
+use crate::diff::{self, Change, Difference};
+use crate::document::{pointer, Document, JsonPath, LoadError, PathSegment, Row};
+use crate::file_ops;
+use crate::file_tree::FileTree;
+use crate::history::History;
+use crate::jsonpath;
+use crate::patch::{self, PatchError};
+use crate::query;
+use crate::search::{self, Replacement, Search};
+
+/// How much memory the undo history may hold on to.
+const HISTORY_SIZE: usize = 64 * 1024 * 1024;
 
 pub enum CurrentScreen {
     Main,
     Editing,
     FileTree,
+    FilteringTree,
+    ManagingFile,
     Exiting,
     Deleting,
+    SavingAs,
+    ConfirmingOverwrite,
+    Patching,
+    ConfirmingPatch,
+    GoingTo,
+    Searching,
+    Replacing,
+    ConfirmingReplace,
+    Querying,
+    SavingQuery,
+    Help,
+    ConfirmingFileOverwrite,
 }
-use ratatui::widgets::{Scrollbar, ScrollbarState};
+use ratatui::layout::Direction;
+use ratatui::widgets::{ListState, ScrollbarState};
 pub enum CurrentlyEditing {
     Key,
     Value,
 }
-pub enum CurrentlyDeleting {
-    Index,
+
+/// A save into a file that already exists, waiting for the user to agree to
+/// replace it.
+pub enum FileSave {
+    SaveAs,
+    Patch,
+    QueryResult,
+}
+
+/// What the file popup opened from the tree panel does.
+pub enum FileOperation {
+    Create,
+    Rename,
+    Duplicate,
+    Delete,
+}
+
+/// An open document with everything that belongs to it alone. The active tab
+/// lives in the fields of `App`, the others wait in `App::tabs` until switched to.
+pub struct Tab {
+    pub document: Document,
+    history: History,
+    pub dirty: bool,
+    pub file_path: Option<PathBuf>,
+    name: Option<String>,
+    pub json_list_state: ListState,
+    pub expanded: HashSet<JsonPath>,
+    pub vertical_scroll_state: ScrollbarState,
+    vertical_scroll: usize,
+}
+
+impl Tab {
+    fn new() -> Tab {
+        Tab {
+            document: Document::new(),
+            history: History::new(HISTORY_SIZE),
+            dirty: false,
+            file_path: None,
+            name: None,
+            json_list_state: ListState::default().with_selected(Some(0)),
+            expanded: HashSet::new(),
+            vertical_scroll_state: ScrollbarState::default(),
+            vertical_scroll: 0,
+        }
+    }
+}
+
+/// The json area divided in two panes, each showing a tab.
+#[derive(Clone, Copy)]
+pub struct Split {
+    pub direction: Direction, // `Horizontal` puts the panes side by side
+    pub panes: [usize; 2],    // the tab shown in each pane, one of them is the active tab
+}
+
+/// The differences between the documents of the two panes, the left (or top)
+/// pane being the old side.
+pub struct DiffView {
+    pub differences: Vec<Difference>,
+    pub current: usize, // the difference that is shown and taken from either side
+}
+
+/// What the patch popup does with the path typed into it.
+pub enum PatchAction {
+    Export,      // write the session's changes as a JSON Patch
+    Apply,       // apply a JSON Patch file
+    ApplyMerge,  // apply a JSON Merge Patch file
+    ExportMerge, // write the merge patch between the panes, or the file on disk and the document
+}
+
+/// A patch that was read and tried out, waiting for confirmation.
+pub struct PendingPatch {
+    pub label: String,
+    pub result: Value,
+    pub operations: Vec<Value>,
+    pub differences: Vec<Difference>,
 }
 
 pub struct App {
-    pub key_input: String,   // the currently being edited json key.
-    pub value_input: String, // the currently being edited json value.
-    pub delete_index: String,
-    pub pairs: HashMap<String, String>, // The representation of our key and value pairs with serde Serialize support
+    pub key_input: String,              // the currently being edited json key.
+    pub value_input: String,            // the currently being edited json value.
+    pub value_as_string: bool, // store the value box verbatim as a string instead of parsing it as json
+    pub editing_path: Option<JsonPath>, // the pair being edited in place, `None` when the popup creates a new pair
+    pub overwrite_confirmed: bool, // the user agreed to replace the existing pair the popup would overwrite
+    pub document: Document, // The json document tree being edited, every object, array and scalar in it
+    pub history: History,   // snapshots of the document before each change, for undo and redo
+    pub dirty: bool,        // the document has changes that are not saved yet
     pub current_screen: CurrentScreen, // the current screen the user is looking at, and will later determine what is rendered.
     pub currently_editing: Option<CurrentlyEditing>,
-    pub currently_deleting: Option<CurrentlyDeleting>, // the optional state containing which of the key or value pair the user is editing. It is an option, because when the user is not directly editing a key-value pair, this will be set to `None`.
-    pub vertical_scroll_state: Option<ScrollbarState>,
+    pub json_list_state: ListState, // the selected row of the json list, every action works on this row
+    pub expanded: HashSet<JsonPath>, // the objects and arrays whose children are shown in the json tree
+    pub pending_key: Option<char>,   // the first key of a two key command such as `z3`
+    pub vertical_scroll_state: ScrollbarState,
     //pub horizontal_scroll_state: Option<ScrollbarState>,
     pub vertical_scroll: usize,
     //pub horizontal_scroll: usize,
+    pub json_page_height: usize, // how many rows of the json list fit on screen, for page up/down
+    pub file_tree: FileTree,     // the directories and files shown in the tree panel
+    pub status_message: Option<String>, // feedback for the last action, e.g. why a file could not be opened
+    pub file_path: Option<PathBuf>, // where the document is saved to, `None` until it has been opened or saved as
+    pub save_as_input: String,      // the path being typed into the save as popup
+    pub quit_after_save: bool,      // set when the save as popup was opened from the exit popup
+    pub pending_file_save: Option<(FileSave, PathBuf)>, // the save waiting for confirmation and the file it would replace
+    pub file_overwrite_confirmed: bool,                 // the user agreed to replace that file
+    pub backup: bool, // keep a `file.json~` copy of the previous contents on every save
+    pub file_operation: Option<FileOperation>, // the operation the file popup is asking about
+    pub file_operation_source: PathBuf, // the tree entry the operation works on
+    pub file_operation_input: String, // the path being typed into the file popup
+    pub tabs: Vec<Tab>, // every open document, the active one's slot is empty while it is in the fields above
+    pub active_tab: usize, // the index of the tab being edited
+    pub split: Option<Split>, // set while two tabs are shown next to each other
+    pub tab_name: Option<String>, // shown instead of the file name, e.g. for the on-disk copy in a diff
+    pub diff: Option<DiffView>,   // set while the two panes are compared
+    pub patch_action: Option<PatchAction>, // what the patch popup is asking a path for
+    pub patch_input: String,      // the path being typed into the patch popup
+    pub pending_patch: Option<PendingPatch>, // the patch being previewed
+    pub goto_input: String,       // the JSON Pointer or JSONPath typed into the go to popup
+    pub matches: Vec<JsonPath>,   // the paths the last go to or search found, cycled with n and N
+    pub current_match: usize,     // the match that is selected
+    pub search: Search,           // the incremental search typed after /
+    search_start: Option<JsonPath>, // the selection when the search started, restored on cancel
+    search_expanded: HashSet<JsonPath>, // what was expanded when the search started, restored on cancel
+    pub replace_input: String,          // what the matches of the search are replaced with
+    pub typing_replacement: bool,       // the focus is on the replacement box of the replace popup
+    pub replace_in_selection: bool, // only replace in the subtree selected when the popup opened
+    replace_under: JsonPath,        // that subtree
+    pub replacements: Vec<Replacement>, // the changes being previewed
+    pub replacements_state: ListState, // the change highlighted in the preview
+    pub query_input: String,        // the jq-like query typed into the query prompt
+    pub query_result: Result<Vec<Value>, String>, // its outputs for the document, kept up to date while typing
+    pub query_scroll: u16,                        // how far the result pane is scrolled down
+    pub query_output_input: String,               // where to save the query result
+    pub help_scroll: u16,                         // how far the list of keys is scrolled down
 }
 
 impl App {
@@ -37,45 +185,502 @@ impl App {
         App {
             key_input: String::new(),
             value_input: String::new(),
-            delete_index: String::new(),
-            pairs: HashMap::new(),
+            value_as_string: false,
+            editing_path: None,
+            overwrite_confirmed: false,
+            document: Document::new(),
+            history: History::new(HISTORY_SIZE),
+            dirty: false,
             current_screen: CurrentScreen::Main,
             currently_editing: None,
-            currently_deleting: None,
-            vertical_scroll_state: None,
+            json_list_state: ListState::default().with_selected(Some(0)),
+            expanded: HashSet::new(),
+            pending_key: None,
+            vertical_scroll_state: ScrollbarState::default(),
             vertical_scroll: 0,
+            json_page_height: 1,
+            file_tree: FileTree::new(Path::new(".")),
+            status_message: None,
+            file_path: None,
+            save_as_input: String::new(),
+            quit_after_save: false,
+            pending_file_save: None,
+            file_overwrite_confirmed: false,
+            backup: false,
+            file_operation: None,
+            file_operation_source: PathBuf::new(),
+            file_operation_input: String::new(),
+            tabs: vec![Tab::new()],
+            active_tab: 0,
+            split: None,
+            tab_name: None,
+            diff: None,
+            patch_action: None,
+            patch_input: String::new(),
+            pending_patch: None,
+            goto_input: String::new(),
+            matches: Vec::new(),
+            current_match: 0,
+            search: Search::new(),
+            search_start: None,
+            search_expanded: HashSet::new(),
+            replace_input: String::new(),
+            typing_replacement: false,
+            replace_in_selection: false,
+            replace_under: JsonPath::new(),
+            replacements: Vec::new(),
+            replacements_state: ListState::default(),
+            query_input: String::new(),
+            query_result: Ok(Vec::new()),
+            query_scroll: 0,
+            query_output_input: String::new(),
+            help_scroll: 0,
         }
     }
 
-    pub fn save_key_value(&mut self) {
-        self.pairs
-            .insert(self.key_input.clone(), self.value_input.clone());
+    /// Opens `path` in a new tab, or in the current one when that is an empty
+    /// unnamed document. A file that is already open is switched to instead. On
+    /// failure nothing changes and the reason is shown in the status bar.
+    pub fn open_file(&mut self, path: &Path) {
+        if let Some(index) = self.tab_index_of(path) {
+            self.switch_tab(index);
+            self.status_message = Some(format!("switched to {}", path.display()));
+            return;
+        }
+        let (document, message) = match Document::load(path) {
+            Ok(document) => (document, format!("opened {}", path.display())),
+            // a path that does not exist yet starts a new document that will be saved there
+            Err(LoadError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                (Document::new(), format!("new file {}", path.display()))
+            }
+            Err(err) => {
+                self.status_message = Some(format!("{}: {err}", path.display()));
+                return;
+            }
+        };
+        if self.dirty || self.file_path.is_some() || self.tab_name.is_some() {
+            self.new_tab();
+        }
+        self.document = document;
+        self.history = History::new(HISTORY_SIZE);
+        self.expand_to_depth(1);
+        self.select(0);
+        self.file_path = Some(path.to_path_buf());
+        self.status_message = Some(message);
+    }
+
+    /// Opens the file selected in the tree panel, or expands/collapses the
+    /// selected directory. Returns true when a file was opened.
+    pub fn open_selected_tree_entry(&mut self) -> bool {
+        let Some(node) = self.file_tree.selected() else {
+            return false;
+        };
+        if node.is_dir {
+            self.file_tree.toggle_selected();
+            return false;
+        }
+        let path = node.path.clone();
+        self.open_file(&path);
+        self.file_path.as_deref() == Some(path.as_path())
+    }
+
+    /// The value box interpreted as a json literal (`42`, `true`, `[1,2]`, ...), or
+    /// taken verbatim when the value is forced to be a string.
+    pub fn parsed_value(&self) -> serde_json::Result<Value> {
+        if self.value_as_string {
+            Ok(Value::String(self.value_input.clone()))
+        } else {
+            serde_json::from_str(&self.value_input)
+        }
+    }
+
+    /// Opens the editing popup pre-filled with the selected pair. Strings are shown
+    /// without quotes and saved as strings again unless that is toggled off.
+    pub fn start_editing_selected(&mut self) -> bool {
+        let Some(path) = self.selected_path() else {
+            return false;
+        };
+        let Some(value) = self.document.get(&path) else {
+            return false;
+        };
+        (self.value_input, self.value_as_string) = match value {
+            Value::String(text) => (text.clone(), true),
+            other => (other.to_string(), false),
+        };
+        self.key_input = match path.last() {
+            Some(PathSegment::Key(key)) => key.clone(),
+            Some(PathSegment::Index(index)) => index.to_string(),
+            None => String::new(),
+        };
+        self.editing_path = Some(path);
+        self.currently_editing = Some(CurrentlyEditing::Value);
+        true
+    }
+
+    pub fn cancel_editing(&mut self) {
         self.key_input = String::new();
         self.value_input = String::new();
+        self.value_as_string = false;
+        self.editing_path = None;
+        self.overwrite_confirmed = false;
         self.currently_editing = None;
     }
 
-    // pub fn save_deleting_index(&mut self) {
-    //     self.delete_index
-    //         .insert(self.key_input.clone(), self.value_input.clone());
-    //     self.key_input = String::new();
-    //     self.value_input = String::new();
-    //     self.currently_editing = None;
-    // }
+    /// Stores the pair from the editing popup. Returns false and keeps the popup
+    /// open when the value is not valid json or the key can not be set, or asks
+    /// for confirmation first when a different existing pair would be replaced.
+    pub fn save_key_value(&mut self) -> bool {
+        let Ok(value) = self.parsed_value() else {
+            // other errors are shown under the value box as it is typed
+            if self.value_input.is_empty() {
+                self.status_message = Some(String::from(
+                    "empty value, type one or press ctrl+t to store an empty string",
+                ));
+            }
+            return false;
+        };
+        let path = match &self.editing_path {
+            Some(old_path) => match self.renamed_path(old_path) {
+                Ok(path) => path,
+                Err(message) => {
+                    self.status_message = Some(message);
+                    return false;
+                }
+            },
+            None => self.new_pair_path(),
+        };
+
+        let replaces_other =
+            self.editing_path.as_ref() != Some(&path) && self.document.get(&path).is_some();
+        if replaces_other && !self.overwrite_confirmed {
+            self.current_screen = CurrentScreen::ConfirmingOverwrite;
+            return false;
+        }
+
+        let before = self.document.root.clone();
+        let (result, label, target, operations) = match self.editing_path.clone() {
+            // renaming keeps the pair where it was in its object
+            Some(old_path) if old_path != path => {
+                let mut operations = vec![patch::move_operation(&old_path, &path)];
+                if self.document.get(&old_path) != Some(&value) {
+                    operations.push(patch::replace_operation(&path, value.clone()));
+                }
+                let renamed = self.document.rename(&old_path, &self.key_input);
+                if renamed.is_ok() {
+                    self.forget_expanded(&path);
+                    self.move_expanded(&old_path, &path);
+                }
+                (
+                    renamed.and_then(|_| self.document.set(&path, value)),
+                    format!("rename {} to {}", pointer(&old_path), pointer(&path)),
+                    None,
+                    Some(operations),
+                )
+            }
+            // repeated edits of one value undo together
+            Some(_) => (
+                self.document.set(&path, value.clone()),
+                format!("edit {}", pointer(&path)),
+                Some(pointer(&path)),
+                Some(vec![patch::replace_operation(&path, value)]),
+            ),
+            // worked out afterwards, as missing parents are added along the way
+            None => (
+                self.document.set(&path, value),
+                format!("add {}", pointer(&path)),
+                None,
+                None,
+            ),
+        };
+        if let Err(err) = result {
+            self.document.root = before;
+            self.status_message = Some(err.to_string());
+            self.overwrite_confirmed = false;
+            return false;
+        }
+        match operations {
+            Some(operations) => self.record_operations(label, operations, before, target),
+            None => self.record_change(label, before, target),
+        }
+        self.select_path(&path);
+        self.cancel_editing();
+        true
+    }
+
+    /// The path of the pair being edited once the key box is applied to it.
+    fn renamed_path(&self, old_path: &[PathSegment]) -> Result<JsonPath, String> {
+        let mut path = old_path.to_vec();
+        match path.last_mut() {
+            Some(PathSegment::Key(key)) => *key = self.key_input.clone(),
+            Some(PathSegment::Index(index)) if self.key_input == index.to_string() => {}
+            Some(PathSegment::Index(_)) => {
+                return Err(String::from("array items can not be renamed"));
+            }
+            None => {}
+        }
+        Ok(path)
+    }
+
+    /// Where the pair typed into the editing popup goes. A key starting with '/' is
+    /// a JSON Pointer from the document root, anything else is a key of the
+    /// selected object (or of the selection's parent when a scalar is selected).
+    /// In arrays the key is an index, and anything that is not a number appends.
+    pub fn new_pair_path(&self) -> JsonPath {
+        if self.key_input.starts_with('/') {
+            if let Ok(path) = self.document.parse_pointer(&self.key_input) {
+                return path;
+            }
+        }
+        let mut path = self.insertion_parent();
+        match self.document.get(&path) {
+            Some(Value::Array(items)) => path.push(PathSegment::Index(
+                self.key_input.parse().unwrap_or(items.len()),
+            )),
+            _ => path.push(PathSegment::Key(self.key_input.clone())),
+        }
+        path
+    }
 
+    /// The container new pairs are added to: the selected row if it is an object
+    /// or array, otherwise the object or array holding it.
+    pub fn insertion_parent(&self) -> JsonPath {
+        let Some(mut path) = self.selected_path() else {
+            return JsonPath::new();
+        };
+        match self.document.get(&path) {
+            Some(Value::Object(_)) | Some(Value::Array(_)) => {}
+            _ => {
+                path.pop();
+            }
+        }
+        path
+    }
+
+    /// Deletes the selected row, along with everything nested in it.
     pub fn delete_key(&mut self) {
-        // Parse String as int
-        match self.delete_index.parse::<usize>() {
-            Ok(index) => {
-                if let Some(pair_to_del) = self.pairs.iter().nth(index) {
-                    let key_to_del = pair_to_del.0.clone();
-                    self.pairs.remove(&key_to_del);
+        if let Some(path) = self.selected_path() {
+            let before = self.document.root.clone();
+            match self.document.remove(&path) {
+                Ok(_) => {
+                    self.record_operations(
+                        format!("delete {}", pointer(&path)),
+                        vec![patch::remove_operation(&path)],
+                        before,
+                        None,
+                    );
+                    self.forget_expanded(&path);
+                    self.status_message = Some(format!("deleted {}", pointer(&path)));
                 }
+                Err(err) => self.status_message = Some(err.to_string()),
+            }
+            self.select(self.vertical_scroll);
+        }
+    }
+
+    /// The rows currently visible in the json tree.
+    pub fn rows(&self) -> Vec<Row<'_>> {
+        self.document.rows(&|path| self.expanded.contains(path))
+    }
+
+    pub fn selected_path(&self) -> Option<JsonPath> {
+        let index = self.json_list_state.selected()?;
+        self.rows().into_iter().nth(index).map(|row| row.path)
+    }
+
+    /// Expands or collapses the selected object or array.
+    pub fn toggle_expanded(&mut self) {
+        if let Some(path) = self.selected_path() {
+            if !self.expanded.remove(&path) && self.is_container(&path) {
+                self.expanded.insert(path);
             }
-            Err(_) => {}
         }
     }
 
+    pub fn expand_selected(&mut self) {
+        if let Some(path) = self.selected_path() {
+            if self.is_container(&path) {
+                self.expanded.insert(path);
+            }
+        }
+    }
+
+    /// Collapses the selected object or array, or when there is nothing to
+    /// collapse moves the selection up to the parent instead.
+    pub fn collapse_selected(&mut self) {
+        if let Some(mut path) = self.selected_path() {
+            if !self.expanded.remove(&path) && path.len() > 1 {
+                path.pop();
+                self.expanded.remove(&path);
+                self.select_path(&path);
+            }
+        }
+    }
+
+    pub fn expand_all(&mut self) {
+        let selected = self.selected_path();
+        self.expanded = self.document.container_paths().into_iter().collect();
+        self.reselect(selected);
+    }
+
+    pub fn collapse_all(&mut self) {
+        self.expand_to_depth(1);
+    }
+
+    /// Shows the first `depth` levels of the document and collapses everything below.
+    pub fn expand_to_depth(&mut self, depth: usize) {
+        let selected = self.selected_path();
+        self.expanded = self
+            .document
+            .container_paths()
+            .into_iter()
+            .filter(|path| path.len() < depth)
+            .collect();
+        self.reselect(selected);
+    }
+
+    /// Selects `path` again after the visible rows changed, or its closest
+    /// ancestor that is still visible.
+    fn reselect(&mut self, selected: Option<JsonPath>) {
+        let Some(mut path) = selected else {
+            return self.select(0);
+        };
+        while !path.is_empty() {
+            if let Some(index) = self.rows().iter().position(|row| row.path == path) {
+                return self.select(index);
+            }
+            path.pop();
+        }
+        self.select(0);
+    }
+
+    fn is_container(&self, path: &[PathSegment]) -> bool {
+        matches!(
+            self.document.get(path),
+            Some(Value::Object(_)) | Some(Value::Array(_))
+        )
+    }
+
+    /// Drops the expansion state of a removed subtree. Later items of an array
+    /// moved up by one, so their state moves with them.
+    fn forget_expanded(&mut self, removed: &[PathSegment]) {
+        let Some((PathSegment::Index(removed_index), parent)) = removed.split_last() else {
+            self.expanded.retain(|path| !path.starts_with(removed));
+            return;
+        };
+        self.expanded = std::mem::take(&mut self.expanded)
+            .into_iter()
+            .filter(|path| !path.starts_with(removed))
+            .map(|mut path| {
+                if path.len() > parent.len() && path.starts_with(parent) {
+                    if let PathSegment::Index(index) = &mut path[parent.len()] {
+                        if *index > *removed_index {
+                            *index -= 1;
+                        }
+                    }
+                }
+                path
+            })
+            .collect();
+    }
+
+    /// Carries the expansion state of a renamed subtree over to its new path.
+    fn move_expanded(&mut self, from: &[PathSegment], to: &[PathSegment]) {
+        self.expanded = std::mem::take(&mut self.expanded)
+            .into_iter()
+            .map(|path| {
+                if path.starts_with(from) {
+                    to.iter().chain(&path[from.len()..]).cloned().collect()
+                } else {
+                    path
+                }
+            })
+            .collect();
+    }
+
+    /// Moves the selection to row `index`, clamped to the rows of the document.
+    pub fn select(&mut self, index: usize) {
+        let row_count = self.rows().len();
+        let index = index.min(row_count.saturating_sub(1));
+        self.json_list_state.select(Some(index));
+        self.vertical_scroll = index;
+        self.vertical_scroll_state = self
+            .vertical_scroll_state
+            .content_length(row_count)
+            .position(index);
+    }
+
+    /// Selects `path`, expanding its parents first so the row is visible.
+    pub fn select_path(&mut self, path: &[PathSegment]) {
+        for depth in 1..path.len() {
+            self.expanded.insert(path[..depth].to_vec());
+        }
+        if let Some(index) = self.rows().iter().position(|row| row.path == path) {
+            self.select(index);
+        }
+    }
+
+    pub fn select_previous(&mut self, rows: usize) {
+        self.select(self.vertical_scroll.saturating_sub(rows));
+    }
+
+    pub fn select_next(&mut self, rows: usize) {
+        self.select(self.vertical_scroll.saturating_add(rows));
+    }
+
+    /// Sorts every object's keys alphabetically, keeping the same pair selected.
+    pub fn sort_keys(&mut self) {
+        let selected = self.selected_path();
+        let before = self.document.root.clone();
+        self.document.sort_keys();
+        self.record_change(String::from("sort keys"), before, None);
+        self.reselect(selected);
+        self.status_message = Some(String::from("sorted keys alphabetically"));
+    }
+
+    /// Records a change made to the document since `before`, working out the
+    /// JSON Patch operations for it from the differences.
+    fn record_change(&mut self, label: String, before: Value, target: Option<String>) {
+        let operations = patch::operations_between(&before, &self.document.root);
+        self.record_operations(label, operations, before, target);
+    }
+
+    /// Every change to the document goes through here so it can be undone and
+    /// exported as part of the session's patch.
+    fn record_operations(
+        &mut self,
+        label: String,
+        operations: Vec<Value>,
+        before: Value,
+        target: Option<String>,
+    ) {
+        self.history.push(label, operations, before, target);
+        self.dirty = !self.history.is_saved();
+    }
+
+    pub fn undo(&mut self) {
+        let selected = self.selected_path();
+        match self.history.undo(&mut self.document.root) {
+            Some(label) => {
+                self.dirty = !self.history.is_saved();
+                self.status_message = Some(format!("undid {label}"));
+            }
+            None => self.status_message = Some(String::from("nothing to undo")),
+        }
+        self.reselect(selected);
+    }
+
+    pub fn redo(&mut self) {
+        let selected = self.selected_path();
+        match self.history.redo(&mut self.document.root) {
+            Some(label) => {
+                self.dirty = !self.history.is_saved();
+                self.status_message = Some(format!("redid {label}"));
+            }
+            None => self.status_message = Some(String::from("nothing to redo")),
+        }
+        self.reselect(selected);
+    }
+
     pub fn toggle_editing(&mut self) {
         if let Some(edit_mode) = &self.currently_editing {
             match edit_mode {
@@ -87,11 +692,1132 @@ impl App {
         }
     }
 
-    pub fn print_json(&self) -> serde_json::Result<()> {
-        // create and write json file
-        let json_string = serde_json::to_string_pretty(&self.pairs)?;
-        let mut file = File::create("output.json")?;
-        file.write_all(json_string.as_bytes())?;
-        Ok(())
+    /// Writes the document to `file_path`. Returns false when there is no path
+    /// yet or the write failed, with the reason in the status bar.
+    pub fn save(&mut self) -> bool {
+        let Some(path) = self.file_path.clone() else {
+            self.status_message = Some(String::from("no file name, use save as"));
+            return false;
+        };
+        match self.document.save(&path, self.backup) {
+            Ok(()) => {
+                self.dirty = false;
+                self.history.mark_saved();
+                self.status_message = Some(format!("saved {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.status_message = Some(format!("could not save {}: {err}", path.display()));
+                false
+            }
+        }
+    }
+
+    /// Saves to the path typed into the save as popup, which becomes the new
+    /// `file_path`. Asks first when that would replace another existing file.
+    pub fn save_as(&mut self) -> bool {
+        if self.save_as_input.is_empty() {
+            return false;
+        }
+        let target = PathBuf::from(&self.save_as_input);
+        if self.file_path.as_ref() != Some(&target)
+            && self.asks_to_overwrite(FileSave::SaveAs, &target)
+        {
+            return false;
+        }
+        let previous = self.file_path.replace(PathBuf::from(&self.save_as_input));
+        if self.save() {
+            self.save_as_input = String::new();
+            true
+        } else {
+            self.file_path = previous;
+            false
+        }
+    }
+
+    /// Switches to the overwrite confirmation when `path` exists and the user
+    /// has not agreed to replace it yet. The confirmation is used up either way.
+    fn asks_to_overwrite(&mut self, save: FileSave, path: &Path) -> bool {
+        let confirmed = std::mem::take(&mut self.file_overwrite_confirmed);
+        if confirmed || fs::symlink_metadata(path).is_err() {
+            return false;
+        }
+        self.pending_file_save = Some((save, path.to_path_buf()));
+        self.current_screen = CurrentScreen::ConfirmingFileOverwrite;
+        true
+    }
+
+    /// Opens the file popup for `operation` on the entry selected in the tree,
+    /// with the path box filled in as a starting point. A new file goes into the
+    /// selected directory, or next to the selected file.
+    pub fn start_file_operation(&mut self, operation: FileOperation) {
+        let selected = self
+            .file_tree
+            .selected()
+            .map(|node| (node.path.clone(), node.is_dir));
+        let (source, input) = match (&operation, selected) {
+            (FileOperation::Create, Some((dir, true))) => (dir.clone(), dir.join("")),
+            (FileOperation::Create, Some((file, false))) => {
+                let dir = file.parent().unwrap_or(&self.file_tree.root).to_path_buf();
+                (dir.clone(), dir.join(""))
+            }
+            (FileOperation::Create, None) => {
+                (self.file_tree.root.clone(), self.file_tree.root.join(""))
+            }
+            (FileOperation::Rename | FileOperation::Delete, Some((path, _))) => {
+                (path.clone(), path)
+            }
+            (FileOperation::Duplicate, Some((path, _))) => {
+                (path.clone(), file_ops::copy_name(&path))
+            }
+            (_, None) => return,
+        };
+        self.file_operation_source = source;
+        self.file_operation_input = input.display().to_string();
+        self.file_operation = Some(operation);
+        self.current_screen = CurrentScreen::ManagingFile;
+    }
+
+    pub fn cancel_file_operation(&mut self) {
+        self.file_operation = None;
+        self.file_operation_input = String::new();
+        self.current_screen = CurrentScreen::FileTree;
+    }
+
+    /// Carries out the operation of the file popup. On failure the popup stays
+    /// open with the reason in the status bar.
+    pub fn finish_file_operation(&mut self) {
+        let Some(operation) = &self.file_operation else {
+            return;
+        };
+        let source = self.file_operation_source.clone();
+        let target = PathBuf::from(&self.file_operation_input);
+        let needs_name = !matches!(operation, FileOperation::Delete);
+        if needs_name
+            && (self.file_operation_input.is_empty() || self.file_operation_input.ends_with('/'))
+        {
+            self.status_message = Some(String::from("enter a file name"));
+            return;
+        }
+
+        let below = self.open_files_below(&source);
+        let result = match operation {
+            FileOperation::Create => {
+                file_ops::create(&target).map(|()| format!("created {}", target.display()))
+            }
+            FileOperation::Rename => file_ops::rename(&source, &target).map(|()| {
+                // keep saving open documents to where their files went
+                for ((file_path, _, _), moved) in self.open_files_mut().zip(below) {
+                    if let Some(moved) = moved {
+                        // joining an empty path would add a trailing slash
+                        *file_path = Some(if moved.as_os_str().is_empty() {
+                            target.clone()
+                        } else {
+                            target.join(moved)
+                        });
+                    }
+                }
+                format!("moved {} to {}", source.display(), target.display())
+            }),
+            FileOperation::Duplicate => file_ops::duplicate(&source, &target)
+                .map(|()| format!("copied {} to {}", source.display(), target.display())),
+            FileOperation::Delete => file_ops::trash(&source).map(|trashed| {
+                // open documents from there now only live in memory
+                for ((_, dirty, history), moved) in self.open_files_mut().zip(below) {
+                    if moved.is_some() {
+                        *dirty = true;
+                        history.forget_saved();
+                    }
+                }
+                format!("moved {} to {}", source.display(), trashed.display())
+            }),
+        };
+        match result {
+            Ok(message) => {
+                self.status_message = Some(message);
+                self.file_tree.refresh();
+                self.file_tree.select_path(&target);
+                self.cancel_file_operation();
+            }
+            Err(err) => self.status_message = Some(err.to_string()),
+        }
+    }
+
+    /// Shows `root` in the tree panel instead of the current directory.
+    pub fn change_root(&mut self, root: &Path) {
+        if !root.is_dir() {
+            self.status_message = Some(format!("{} is not a directory", root.display()));
+            return;
+        }
+        self.file_tree.change_root(root);
+    }
+
+    /// Makes the directory selected in the tree the root, or the directory of
+    /// the selected file.
+    pub fn change_root_to_selected(&mut self) {
+        let Some(node) = self.file_tree.selected() else {
+            return;
+        };
+        let root = match (node.is_dir, node.path.parent()) {
+            (true, _) => node.path.clone(),
+            (false, Some(parent)) => parent.to_path_buf(),
+            (false, None) => return,
+        };
+        self.change_root(&root);
+    }
+
+    /// Opens an empty unnamed document in a new tab after the others.
+    pub fn new_tab(&mut self) {
+        self.tabs.push(Tab::new());
+        self.switch_tab(self.tabs.len() - 1);
+    }
+
+    /// Makes tab `index` the active one, keeping the state of the current tab.
+    pub fn switch_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() || index == self.active_tab {
+            return;
+        }
+        // the focused pane shows the new tab, unless the other pane already does
+        if let Some(split) = &mut self.split {
+            if !split.panes.contains(&index) {
+                for pane in &mut split.panes {
+                    if *pane == self.active_tab {
+                        *pane = index;
+                    }
+                }
+            }
+        }
+        self.tabs[self.active_tab] = self.take_tab();
+        let next = std::mem::replace(&mut self.tabs[index], Tab::new());
+        self.put_tab(next);
+        self.active_tab = index;
+    }
+
+    pub fn next_tab(&mut self) {
+        self.switch_tab((self.active_tab + 1) % self.tabs.len());
+    }
+
+    pub fn previous_tab(&mut self) {
+        self.switch_tab((self.active_tab + self.tabs.len() - 1) % self.tabs.len());
+    }
+
+    /// Closes the active tab unless it has unsaved changes. The last tab is
+    /// replaced by an empty document instead.
+    pub fn close_tab(&mut self) {
+        if self.dirty {
+            self.status_message = Some(String::from(
+                "unsaved changes, save (CTRL+s) or undo them before closing the tab",
+            ));
+            return;
+        }
+        if self.tabs.len() == 1 {
+            self.put_tab(Tab::new());
+            return;
+        }
+        // the panes would point at the wrong tabs after the removal
+        self.split = None;
+        self.tabs.remove(self.active_tab);
+        self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+        let next = std::mem::replace(&mut self.tabs[self.active_tab], Tab::new());
+        self.put_tab(next);
+    }
+
+    /// Splits the json area in two panes laid out in `direction`, the other pane
+    /// showing the next tab. Asking for the current layout again unsplits.
+    pub fn toggle_split(&mut self, direction: Direction) {
+        match &mut self.split {
+            Some(split) if split.direction == direction => self.split = None,
+            Some(split) => split.direction = direction,
+            None => {
+                if self.tabs.len() == 1 {
+                    self.tabs.push(Tab::new());
+                }
+                let other = (self.active_tab + 1) % self.tabs.len();
+                self.split = Some(Split {
+                    direction,
+                    panes: [self.active_tab, other],
+                });
+            }
+        }
+    }
+
+    /// The tab shown in the pane that does not have focus.
+    fn other_pane(&self) -> Option<usize> {
+        let split = self.split?;
+        split.panes.into_iter().find(|&tab| tab != self.active_tab)
+    }
+
+    pub fn focus_other_pane(&mut self) {
+        if let Some(other) = self.other_pane() {
+            self.switch_tab(other);
+        }
+    }
+
+    /// Copies the selected node into the document of the other pane, at the same
+    /// path, replacing what is there. It is undone in that pane.
+    pub fn copy_to_other_pane(&mut self) {
+        let Some(other) = self.other_pane() else {
+            self.status_message = Some(String::from("split the json area first with | or -"));
+            return;
+        };
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        let Some(value) = self.document.get(&path).cloned() else {
+            return;
+        };
+        if let Some(name) = self.disk_copy_name(other) {
+            self.status_message = Some(format!("{name} can not be changed"));
+            return;
+        }
+        let here = self.active_tab;
+        self.switch_tab(other);
+        let before = self.document.root.clone();
+        match self.document.set(&path, value) {
+            Ok(_) => {
+                self.record_change(format!("copy {}", pointer(&path)), before, None);
+                self.select_path(&path);
+                self.status_message = Some(format!("copied {} to the other pane", pointer(&path)));
+            }
+            Err(err) => self.status_message = Some(err.to_string()),
+        }
+        self.switch_tab(here);
+    }
+
+    /// Starts comparing the documents of the two panes, or stops. Without a split
+    /// the document is compared with its file on disk, which opens beside it.
+    pub fn toggle_diff(&mut self) {
+        if self.diff.take().is_some() {
+            return;
+        }
+        if self.split.is_none() {
+            let Some(path) = self.file_path.clone() else {
+                self.status_message = Some(String::from(
+                    "nothing to compare with, split the json area or save the file first",
+                ));
+                return;
+            };
+            let document = match Document::load(&path) {
+                Ok(document) => document,
+                Err(err) => {
+                    self.status_message = Some(format!("{}: {err}", path.display()));
+                    return;
+                }
+            };
+            let buffer = self.active_tab;
+            self.new_tab();
+            self.document = document;
+            self.tab_name = Some(format!("{} (on disk)", path.display()));
+            self.expand_to_depth(1);
+            self.split = Some(Split {
+                direction: Direction::Horizontal,
+                panes: [self.active_tab, buffer],
+            });
+            self.switch_tab(buffer);
+        }
+        self.diff = Some(DiffView {
+            differences: Vec::new(),
+            current: 0,
+        });
+        self.refresh_diff();
+        self.show_difference();
+    }
+
+    /// Compares the panes again after either document changed. Ends the diff
+    /// when the split is gone.
+    pub fn refresh_diff(&mut self) {
+        let Some(split) = self.split else {
+            self.diff = None;
+            return;
+        };
+        let [left, right] = split.panes;
+        let differences = diff::diff(
+            &self.tab_document(left).root,
+            &self.tab_document(right).root,
+        );
+        if let Some(diff) = &mut self.diff {
+            diff.current = diff.current.min(differences.len().saturating_sub(1));
+            diff.differences = differences;
+        }
+    }
+
+    pub fn next_difference(&mut self) {
+        if let Some(diff) = &mut self.diff {
+            if !diff.differences.is_empty() {
+                diff.current = (diff.current + 1) % diff.differences.len();
+            }
+        }
+        self.show_difference();
+    }
+
+    pub fn previous_difference(&mut self) {
+        if let Some(diff) = &mut self.diff {
+            let count = diff.differences.len();
+            if count > 0 {
+                diff.current = (diff.current + count - 1) % count;
+            }
+        }
+        self.show_difference();
+    }
+
+    /// Selects the current difference in both panes, or its closest parent on
+    /// the side where it does not exist.
+    fn show_difference(&mut self) {
+        let (Some(diff), Some(other)) = (&self.diff, self.other_pane()) else {
+            return;
+        };
+        let Some(difference) = diff.differences.get(diff.current) else {
+            self.status_message = Some(String::from("no differences"));
+            return;
+        };
+        let message = format!(
+            "difference {} of {}: {} {}",
+            diff.current + 1,
+            diff.differences.len(),
+            match difference.change {
+                Change::Added => "added",
+                Change::Removed => "removed",
+                Change::Changed => "changed",
+            },
+            pointer(&difference.path)
+        );
+        let path = difference.path.clone();
+        let here = self.active_tab;
+        for tab in [other, here] {
+            self.switch_tab(tab);
+            let mut shown = path.clone();
+            while !shown.is_empty() && self.document.get(&shown).is_none() {
+                shown.pop();
+            }
+            self.select_path(&shown);
+        }
+        self.status_message = Some(message);
+    }
+
+    /// Resolves the current difference with the value of the left pane, or of
+    /// the right one, removing the path when that side does not have it.
+    pub fn take_side(&mut self, from_left: bool) {
+        let (Some(diff), Some(split)) = (&self.diff, self.split) else {
+            return;
+        };
+        let Some(difference) = diff.differences.get(diff.current) else {
+            return;
+        };
+        let path = difference.path.clone();
+        let [left, right] = split.panes;
+        let (from, to) = if from_left {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        if let Some(name) = self.disk_copy_name(to) {
+            self.status_message = Some(format!("{name} can not be changed, take the other side"));
+            return;
+        }
+        // array items missing before an added one are taken along, in index order
+        let mut paths = vec![path.clone()];
+        if let Some((PathSegment::Index(index), parent)) = path.split_last() {
+            if let Some(Value::Array(items)) = self.tab_document(to).get(parent) {
+                paths = (items.len().min(*index)..=*index)
+                    .map(|index| [parent, &[PathSegment::Index(index)]].concat())
+                    .collect();
+            }
+        }
+        let values: Vec<_> = paths
+            .iter()
+            .map(|path| self.tab_document(from).get(path).cloned())
+            .collect();
+
+        let here = self.active_tab;
+        self.switch_tab(to);
+        let before = self.document.root.clone();
+        let result = paths
+            .iter()
+            .zip(values)
+            .try_for_each(|(path, value)| match value {
+                Some(value) => self.document.set(path, value).map(|_| ()),
+                None => self
+                    .document
+                    .remove(path)
+                    .map(|_| self.forget_expanded(path)),
+            });
+        match result {
+            Ok(()) => {
+                let side = if from_left { "left" } else { "right" };
+                self.record_change(format!("take {side} at {}", pointer(&path)), before, None);
+            }
+            Err(err) => self.status_message = Some(err.to_string()),
+        }
+        self.switch_tab(here);
+        self.refresh_diff();
+        self.show_difference();
+    }
+
+    /// The name of `tab` when it holds the copy of a file on disk opened by a
+    /// diff, which has no file to be saved to and so is not changed.
+    fn disk_copy_name(&self, tab: usize) -> Option<&str> {
+        if tab == self.active_tab {
+            self.tab_name.as_deref()
+        } else {
+            self.tabs[tab].name.as_deref()
+        }
+    }
+
+    fn tab_document(&self, tab: usize) -> &Document {
+        if tab == self.active_tab {
+            &self.document
+        } else {
+            &self.tabs[tab].document
+        }
+    }
+
+    /// The file name of every tab and whether it has unsaved changes, in order.
+    pub fn tab_names(&self) -> Vec<(String, bool)> {
+        (0..self.tabs.len())
+            .map(|index| {
+                let (file_path, name, dirty) = if index == self.active_tab {
+                    (&self.file_path, &self.tab_name, self.dirty)
+                } else {
+                    let tab = &self.tabs[index];
+                    (&tab.file_path, &tab.name, tab.dirty)
+                };
+                let name = name
+                    .clone()
+                    .or_else(|| {
+                        let name = file_path.as_deref()?.file_name()?;
+                        Some(name.to_string_lossy().into_owned())
+                    })
+                    .unwrap_or_else(|| String::from("[No Name]"));
+                (name, dirty)
+            })
+            .collect()
     }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.dirty || self.tabs.iter().any(|tab| tab.dirty)
+    }
+
+    /// Saves every tab with unsaved changes. Stops at the first one that has no
+    /// file name yet or fails to save, leaving it active, and returns false.
+    pub fn save_all(&mut self) -> bool {
+        for index in 0..self.tabs.len() {
+            if index != self.active_tab && !self.tabs[index].dirty {
+                continue;
+            }
+            self.switch_tab(index);
+            if self.dirty && !self.save() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn tab_index_of(&self, path: &Path) -> Option<usize> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.open_files().position(|(file_path, _)| {
+            file_path.as_deref().is_some_and(|open| {
+                fs::canonicalize(open).unwrap_or_else(|_| open.to_path_buf()) == path
+            })
+        })
+    }
+
+    /// The file path and unsaved state of every tab, in order.
+    fn open_files(&self) -> impl Iterator<Item = (&Option<PathBuf>, bool)> + '_ {
+        self.tabs.iter().enumerate().map(|(index, tab)| {
+            if index == self.active_tab {
+                (&self.file_path, self.dirty)
+            } else {
+                (&tab.file_path, tab.dirty)
+            }
+        })
+    }
+
+    /// For each of `open_files_mut`, where its file is relative to `source` if
+    /// it is `source` or inside it. Paths are compared canonicalized, so this
+    /// has to be worked out before the files are moved away.
+    fn open_files_below(&self, source: &Path) -> Vec<Option<PathBuf>> {
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let source = canonical(source);
+        std::iter::once(&self.file_path)
+            .chain(self.tabs.iter().map(|tab| &tab.file_path))
+            .map(|file_path| {
+                let open = canonical(file_path.as_deref()?);
+                open.strip_prefix(&source).ok().map(Path::to_path_buf)
+            })
+            .collect()
+    }
+
+    /// Like `open_files`, for updating them after files were moved.
+    fn open_files_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&mut Option<PathBuf>, &mut bool, &mut History)> + '_ {
+        std::iter::once((&mut self.file_path, &mut self.dirty, &mut self.history)).chain(
+            self.tabs
+                .iter_mut()
+                .map(|tab| (&mut tab.file_path, &mut tab.dirty, &mut tab.history)),
+        )
+    }
+
+    /// Moves the active tab's state out of the fields of `App`.
+    fn take_tab(&mut self) -> Tab {
+        Tab {
+            document: std::mem::replace(&mut self.document, Document::new()),
+            history: std::mem::replace(&mut self.history, History::new(HISTORY_SIZE)),
+            dirty: std::mem::take(&mut self.dirty),
+            file_path: self.file_path.take(),
+            name: self.tab_name.take(),
+            json_list_state: std::mem::take(&mut self.json_list_state),
+            expanded: std::mem::take(&mut self.expanded),
+            vertical_scroll_state: std::mem::take(&mut self.vertical_scroll_state),
+            vertical_scroll: std::mem::take(&mut self.vertical_scroll),
+        }
+    }
+
+    fn put_tab(&mut self, tab: Tab) {
+        self.document = tab.document;
+        self.history = tab.history;
+        self.dirty = tab.dirty;
+        self.file_path = tab.file_path;
+        self.tab_name = tab.name;
+        self.json_list_state = tab.json_list_state;
+        self.expanded = tab.expanded;
+        self.vertical_scroll_state = tab.vertical_scroll_state;
+        self.vertical_scroll = tab.vertical_scroll;
+        // the matches were found in the document that was active
+        self.matches.clear();
+    }
+
+    /// Opens the patch popup for `action`, suggesting a file name next to the
+    /// document for exports.
+    pub fn start_patch_action(&mut self, action: PatchAction) {
+        let suffix = match action {
+            PatchAction::Export => Some("patch.json"),
+            PatchAction::ExportMerge => Some("merge-patch.json"),
+            PatchAction::Apply | PatchAction::ApplyMerge => None,
+        };
+        self.patch_input = match (suffix, &self.file_path) {
+            (Some(suffix), Some(path)) => path.with_extension(suffix).display().to_string(),
+            _ => String::new(),
+        };
+        self.patch_action = Some(action);
+        self.current_screen = CurrentScreen::Patching;
+    }
+
+    pub fn cancel_patch_action(&mut self) {
+        self.patch_action = None;
+        self.patch_input = String::new();
+        self.pending_patch = None;
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Carries out the action of the patch popup. Patches to apply are tried
+    /// out first and shown for confirmation. On failure the popup stays open
+    /// with the reason in the status bar.
+    pub fn finish_patch_action(&mut self) {
+        if self.patch_input.is_empty() {
+            return;
+        }
+        let path = PathBuf::from(&self.patch_input);
+        let exports = matches!(
+            self.patch_action,
+            Some(PatchAction::Export | PatchAction::ExportMerge)
+        );
+        if exports && self.asks_to_overwrite(FileSave::Patch, &path) {
+            return;
+        }
+        let Some(action) = &self.patch_action else {
+            return;
+        };
+        let result = match action {
+            PatchAction::Export => {
+                let operations = self.history.operations();
+                let count = operations.len();
+                save_json(&path, Value::Array(operations))
+                    .map(|()| format!("wrote {count} operations to {}", path.display()))
+            }
+            PatchAction::ExportMerge => self.comparison().and_then(|(from, to)| {
+                save_json(&path, patch::merge_patch_between(&from, &to))
+                    .map(|()| format!("wrote merge patch to {}", path.display()))
+            }),
+            PatchAction::Apply | PatchAction::ApplyMerge => {
+                let merge = matches!(action, PatchAction::ApplyMerge);
+                match self.try_patch(&path, merge) {
+                    Ok(pending) => {
+                        self.pending_patch = Some(pending);
+                        self.current_screen = CurrentScreen::ConfirmingPatch;
+                        return;
+                    }
+                    Err(message) => Err(message),
+                }
+            }
+        };
+        match result {
+            Ok(message) => {
+                self.status_message = Some(message);
+                self.cancel_patch_action();
+            }
+            Err(message) => self.status_message = Some(message),
+        }
+    }
+
+    /// Applies the patch file at `path` to a copy of the document.
+    fn try_patch(&self, path: &Path, merge: bool) -> Result<PendingPatch, String> {
+        let patch = Document::load(path)
+            .map_err(|err| format!("{}: {err}", path.display()))?
+            .root;
+        let mut result = self.document.root.clone();
+        let operations = if merge {
+            patch::merge(&mut result, &patch);
+            patch::operations_between(&self.document.root, &result)
+        } else {
+            patch::apply(&mut result, &patch).map_err(|err: PatchError| err.to_string())?;
+            patch.as_array().cloned().unwrap_or_default()
+        };
+        let kind = if merge { "merge patch" } else { "patch" };
+        Ok(PendingPatch {
+            label: format!("{kind} {}", path.display()),
+            differences: diff::diff(&self.document.root, &result),
+            result,
+            operations,
+        })
+    }
+
+    /// Replaces the document with the previewed patch result, as one undo step.
+    pub fn apply_pending_patch(&mut self) {
+        let Some(pending) = self.pending_patch.take() else {
+            return;
+        };
+        let selected = self.selected_path();
+        let before = std::mem::replace(&mut self.document.root, pending.result);
+        self.status_message = Some(format!(
+            "applied {}, {} changes",
+            pending.label,
+            pending.differences.len()
+        ));
+        self.record_operations(pending.label, pending.operations, before, None);
+        self.reselect(selected);
+        self.cancel_patch_action();
+    }
+
+    /// The old and the new document for a merge patch: the left and right pane,
+    /// or without a split the file on disk and the document.
+    fn comparison(&self) -> Result<(Value, Value), String> {
+        if let Some(split) = self.split {
+            let [left, right] = split.panes;
+            return Ok((
+                self.tab_document(left).root.clone(),
+                self.tab_document(right).root.clone(),
+            ));
+        }
+        let Some(path) = &self.file_path else {
+            return Err(String::from(
+                "nothing to compare with, split the json area or save the file first",
+            ));
+        };
+        let disk = Document::load(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok((disk.root, self.document.root.clone()))
+    }
+
+    /// Selects what the go to popup addresses: a JSON Pointer (`/servers/0`)
+    /// or every match of a JSONPath (`$.servers[*].host`). Returns false and
+    /// keeps the popup open when nothing matches.
+    pub fn go_to(&mut self) -> bool {
+        let input = self.goto_input.trim();
+        let matches = if input.starts_with('$') {
+            match jsonpath::select(&self.document.root, input) {
+                Ok(matches) => matches,
+                Err(message) => {
+                    self.status_message = Some(format!("invalid JSONPath: {message}"));
+                    return false;
+                }
+            }
+        } else {
+            match self.document.parse_pointer(input) {
+                Ok(path) if self.document.get(&path).is_some() => vec![path],
+                Ok(_) => Vec::new(),
+                Err(err) => {
+                    self.status_message = Some(err.to_string());
+                    return false;
+                }
+            }
+        };
+        // the root is not a row of the tree
+        let matches: Vec<JsonPath> = matches
+            .into_iter()
+            .filter(|path| !path.is_empty())
+            .collect();
+        if matches.is_empty() {
+            self.status_message = Some(format!("nothing at {input}"));
+            return false;
+        }
+        self.matches = matches;
+        self.current_match = 0;
+        self.goto_input = String::new();
+        self.show_match();
+        true
+    }
+
+    pub fn next_match(&mut self) {
+        if !self.matches.is_empty() {
+            self.current_match = (self.current_match + 1) % self.matches.len();
+        }
+        self.show_match();
+    }
+
+    pub fn previous_match(&mut self) {
+        let count = self.matches.len();
+        if count > 0 {
+            self.current_match = (self.current_match + count - 1) % count;
+        }
+        self.show_match();
+    }
+
+    fn show_match(&mut self) {
+        let Some(path) = self.matches.get(self.current_match).cloned() else {
+            self.status_message = Some(String::from("no matches"));
+            return;
+        };
+        self.select_path(&path);
+        self.status_message = Some(format!(
+            "match {} of {}: {}",
+            self.current_match + 1,
+            self.matches.len(),
+            pointer(&path)
+        ));
+    }
+
+    pub fn start_search(&mut self) {
+        self.search.query.clear();
+        self.search_start = self.selected_path();
+        self.search_expanded = self.expanded.clone();
+        self.matches.clear();
+        self.current_screen = CurrentScreen::Searching;
+    }
+
+    /// Searches again after the query, mode or scope changed: expands the
+    /// parents of every match, collapsing those of earlier queries, and selects
+    /// the first one at or after where the search started.
+    pub fn update_search(&mut self) {
+        let matches = match self.search.find(&self.document.root) {
+            Ok(matches) => matches,
+            Err(err) => {
+                self.matches.clear();
+                self.status_message = Some(format!("invalid regex: {err}"));
+                return;
+            }
+        };
+        self.expanded = self.search_expanded.clone();
+        for path in &matches {
+            for depth in 1..path.len() {
+                self.expanded.insert(path[..depth].to_vec());
+            }
+        }
+        // every path in document order, to find the first match after the start
+        let order: Vec<JsonPath> = self
+            .document
+            .rows(&|_| true)
+            .into_iter()
+            .map(|row| row.path)
+            .collect();
+        let start = self
+            .search_start
+            .as_ref()
+            .and_then(|start| order.iter().position(|path| path == start))
+            .unwrap_or(0);
+        let first = order[start..]
+            .iter()
+            .find_map(|path| matches.iter().position(|found| found == path))
+            .unwrap_or(0);
+        self.matches = matches;
+        self.current_match = first;
+        if self.matches.is_empty() {
+            if let Some(start) = self.search_start.clone() {
+                self.select_path(&start);
+            }
+            if !self.search.query.is_empty() {
+                self.status_message = Some(String::from("no matches"));
+            }
+        } else {
+            self.show_match();
+        }
+    }
+
+    /// Leaves the search, dropping its matches and going back to where it started.
+    pub fn cancel_search(&mut self) {
+        self.search.query.clear();
+        self.matches.clear();
+        self.expanded = std::mem::take(&mut self.search_expanded);
+        if let Some(start) = self.search_start.take() {
+            self.select_path(&start);
+        }
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Opens the find and replace popup, starting from the last search.
+    pub fn start_replace(&mut self) {
+        self.replace_under = self.selected_path().unwrap_or_default();
+        self.typing_replacement = false;
+        self.current_screen = CurrentScreen::Replacing;
+    }
+
+    /// Where the replace popup replaces: the whole document or the subtree
+    /// selected when it opened.
+    pub fn replace_scope(&self) -> String {
+        if self.replace_in_selection && !self.replace_under.is_empty() {
+            pointer(&self.replace_under)
+        } else {
+            String::from("the whole document")
+        }
+    }
+
+    /// Works out every change the replace would make and shows them for
+    /// confirmation. Returns false and keeps the popup open when there are none.
+    pub fn preview_replacements(&mut self) -> bool {
+        let under = if self.replace_in_selection {
+            self.replace_under.clone()
+        } else {
+            JsonPath::new()
+        };
+        let replacements =
+            match self
+                .search
+                .replacements(&self.document.root, &under, &self.replace_input)
+            {
+                Ok(replacements) => replacements,
+                Err(err) => {
+                    self.status_message = Some(format!("invalid regex: {err}"));
+                    return false;
+                }
+            };
+        if replacements.is_empty() {
+            self.status_message = Some(format!("nothing to replace in {}", self.replace_scope()));
+            return false;
+        }
+        self.replacements = replacements;
+        self.replacements_state.select(Some(0));
+        self.current_screen = CurrentScreen::ConfirmingReplace;
+        true
+    }
+
+    /// Accepts or rejects the highlighted change and moves on to the next one.
+    pub fn decide_replacement(&mut self, accept: bool) {
+        let Some(index) = self.replacements_state.selected() else {
+            return;
+        };
+        if let Some(replacement) = self.replacements.get_mut(index) {
+            if accept && replacement.collides {
+                self.status_message = Some(collision_message(replacement));
+            } else {
+                replacement.accepted = accept;
+            }
+        }
+        if index + 1 < self.replacements.len() {
+            self.replacements_state.select(Some(index + 1));
+        }
+    }
+
+    pub fn toggle_replacement(&mut self) {
+        let index = self.replacements_state.selected().unwrap_or_default();
+        if let Some(replacement) = self.replacements.get_mut(index) {
+            if replacement.collides {
+                self.status_message = Some(collision_message(replacement));
+            } else {
+                replacement.accepted = !replacement.accepted;
+            }
+        }
+    }
+
+    /// Makes the accepted changes, or all of them, as one undoable step.
+    /// Renames onto a key that is already taken are never made.
+    pub fn apply_replacements(&mut self, all: bool) {
+        let selected = self.selected_path();
+        let before = self.document.root.clone();
+        let total = self.replacements.len();
+        let (colliding, accepted): (Vec<Replacement>, Vec<Replacement>) =
+            std::mem::take(&mut self.replacements)
+                .into_iter()
+                .filter(|replacement| all || replacement.accepted || replacement.collides)
+                .partition(|replacement| replacement.collides);
+        let skipped = if colliding.is_empty() {
+            String::new()
+        } else {
+            format!(", skipped {} renames onto existing keys", colliding.len())
+        };
+        self.current_screen = CurrentScreen::Main;
+        if accepted.is_empty() {
+            self.status_message = Some(format!("no changes accepted, nothing replaced{skipped}"));
+            return;
+        }
+
+        let mut operations = Vec::new();
+        for replacement in accepted.iter().filter(|replacement| !replacement.key) {
+            if let Some(value) = self.document.get_mut(&replacement.path) {
+                *value = search::replaced_value(value, replacement.after.clone());
+                operations.push(patch::replace_operation(&replacement.path, value.clone()));
+            }
+        }
+        // renaming from the back renames children before their parents, so
+        // the paths of the keys still to rename stay valid
+        for replacement in accepted.iter().rev().filter(|replacement| replacement.key) {
+            let mut renamed = replacement.path.clone();
+            renamed.pop();
+            renamed.push(PathSegment::Key(replacement.after.clone()));
+            if self
+                .document
+                .rename(&replacement.path, &replacement.after)
+                .is_ok()
+            {
+                operations.push(patch::move_operation(&replacement.path, &renamed));
+                self.forget_expanded(&renamed);
+                self.move_expanded(&replacement.path, &renamed);
+            }
+        }
+        self.record_operations(
+            format!("replace {} with {}", self.search.query, self.replace_input),
+            operations,
+            before,
+            None,
+        );
+        // the matches may have been renamed away
+        self.matches.clear();
+        self.reselect(selected);
+        self.status_message = Some(format!(
+            "replaced {} of {total} matches{skipped}",
+            accepted.len()
+        ));
+    }
+
+    pub fn cancel_replace(&mut self) {
+        self.replacements.clear();
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Opens the query prompt, with the last query run again on the document.
+    pub fn start_query(&mut self) {
+        self.update_query();
+        self.current_screen = CurrentScreen::Querying;
+    }
+
+    pub fn update_query(&mut self) {
+        self.query_result = if self.query_input.trim().is_empty() {
+            Ok(Vec::new())
+        } else {
+            query::run(&self.document.root, &self.query_input)
+        };
+        self.query_scroll = 0;
+    }
+
+    /// The query result as one value: several outputs are collected into an
+    /// array, as `jq --slurp` would.
+    fn query_value(&mut self) -> Option<Value> {
+        match &self.query_result {
+            Ok(outputs) if outputs.is_empty() => {
+                self.status_message = Some(String::from("the query has no result"));
+                None
+            }
+            Ok(outputs) if outputs.len() == 1 => Some(outputs[0].clone()),
+            Ok(outputs) => Some(Value::Array(outputs.clone())),
+            Err(message) => {
+                self.status_message = Some(format!("invalid query: {message}"));
+                None
+            }
+        }
+    }
+
+    /// Replaces the whole document, or only the selected node, with the query
+    /// result and closes the prompt.
+    pub fn replace_with_query(&mut self, selected_only: bool) {
+        let path = if selected_only {
+            match self.selected_path() {
+                Some(path) => path,
+                None => return,
+            }
+        } else {
+            JsonPath::new()
+        };
+        let Some(value) = self.query_value() else {
+            return;
+        };
+        let selected = self.selected_path();
+        let before = self.document.root.clone();
+        if let Err(err) = self.document.set(&path, value.clone()) {
+            self.status_message = Some(err.to_string());
+            return;
+        }
+        let target = match pointer(&path) {
+            root if root.is_empty() => String::from("the document"),
+            target => target,
+        };
+        self.record_operations(
+            format!("replace {target} with {}", self.query_input.trim()),
+            vec![patch::replace_operation(&path, value)],
+            before,
+            None,
+        );
+        // what was below the replaced node is gone
+        self.expanded
+            .retain(|expanded| !(expanded.starts_with(&path) && expanded.len() > path.len()));
+        self.matches.clear();
+        self.reselect(selected);
+        self.update_query();
+        self.status_message = Some(format!("replaced {target} with the query result"));
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Asks where to save the query result, suggesting a name next to the document.
+    pub fn start_saving_query(&mut self) {
+        if self.query_output_input.is_empty() {
+            if let Some(path) = &self.file_path {
+                self.query_output_input = path.with_extension("query.json").display().to_string();
+            }
+        }
+        self.current_screen = CurrentScreen::SavingQuery;
+    }
+
+    pub fn save_query_result(&mut self) {
+        if self.query_output_input.is_empty() {
+            return;
+        }
+        let Some(value) = self.query_value() else {
+            self.current_screen = CurrentScreen::Querying;
+            return;
+        };
+        let path = PathBuf::from(&self.query_output_input);
+        if self.asks_to_overwrite(FileSave::QueryResult, &path) {
+            return;
+        }
+        self.status_message = Some(match save_json(&path, value) {
+            Ok(()) => {
+                self.current_screen = CurrentScreen::Querying;
+                format!("wrote the query result to {}", path.display())
+            }
+            Err(message) => message,
+        });
+    }
+
+    /// Puts the JSON Pointer of the selected row on the clipboard.
+    pub fn copy_selected_pointer(&mut self) {
+        let Some(path) = self.selected_path() else {
+            return;
+        };
+        let selected = pointer(&path);
+        self.status_message = Some(match clipboard::copy(&selected) {
+            Ok(()) => format!("copied {selected}"),
+            Err(err) => format!("could not copy {selected}: {err}"),
+        });
+    }
+}
+
+fn collision_message(replacement: &Replacement) -> String {
+    format!(
+        "cannot rename {} to {}, that key is already taken",
+        pointer(&replacement.path),
+        replacement.after
+    )
+}
+
+fn save_json(path: &Path, value: Value) -> Result<(), String> {
+    Document { root: value }
+        .save(path, false)
+        .map_err(|err| format!("could not save {}: {err}", path.display()))
 }
@@ -0,0 +1,97 @@
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Replaces the contents of `path` without ever leaving a half written file behind.
+///
+/// The data goes to a temporary file next to the target, is flushed to disk and
+/// then renamed over the original, so readers see either the old or the new
+/// contents. The original file's permissions are kept, and with `backup` the
+/// previous contents are copied to `path~` first.
+pub fn write_atomic(path: &Path, contents: &[u8], backup: bool) -> io::Result<()> {
+    // write through symlinks instead of replacing the link itself
+    let target = match fs::canonicalize(path) {
+        Ok(resolved) => resolved,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(err) => return Err(err),
+    };
+    let existing = fs::metadata(&target).ok();
+
+    let permissions = existing.as_ref().map(|metadata| metadata.permissions());
+    let (temp_path, mut temp_file) = create_temp_file(&target, permissions.as_ref())?;
+    let result = (|| {
+        // before any bytes are written, so a private file is never readable by others
+        if let Some(permissions) = permissions {
+            temp_file.set_permissions(permissions)?;
+        }
+        temp_file.write_all(contents)?;
+        temp_file.sync_all()?;
+        drop(temp_file);
+
+        if backup && existing.is_some() {
+            fs::copy(&target, backup_path(&target))?;
+        }
+        fs::rename(&temp_path, &target)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // make the rename itself durable
+    if let Some(dir) = parent_dir(&target) {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// `file.json` -> `file.json~`
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("~");
+    PathBuf::from(name)
+}
+
+fn parent_dir(path: &Path) -> Option<&Path> {
+    match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Some(Path::new(".")),
+        parent => parent,
+    }
+}
+
+/// Creates a new, uniquely named file in the same directory as `target`, so the
+/// final rename never has to cross file systems. On unix it is created with the
+/// mode of `permissions` when given.
+fn create_temp_file(
+    target: &Path,
+    permissions: Option<&Permissions>,
+) -> io::Result<(PathBuf, File)> {
+    let dir = parent_dir(target).unwrap_or(Path::new("."));
+    let name = target
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    for attempt in 0.. {
+        let temp_path = dir.join(format!(".{name}.{}.{attempt}.tmp", std::process::id()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if let Some(permissions) = permissions {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(permissions.mode() & 0o777);
+        }
+        #[cfg(not(unix))]
+        let _ = permissions;
+        match options.open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
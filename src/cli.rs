@@ -0,0 +1,166 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::document::{to_string_pretty, Document, LoadError};
+
+/// Exit code for success.
+pub const EXIT_OK: i32 = 0;
+/// Exit code when the path does not exist or the file is not valid json.
+pub const EXIT_NOT_FOUND: i32 = 1;
+/// Exit code for bad arguments and i/o errors.
+pub const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "usage:
+  ratatui-json-editor [file] [-o|--output <file>] [-b|--backup] [-r|--root <dir>]
+  ratatui-json-editor get <file> <pointer>
+  ratatui-json-editor set <file> <pointer> <json>
+  ratatui-json-editor delete <file> <pointer>
+  ratatui-json-editor fmt <file> [-w|--write]
+  ratatui-json-editor validate <file>
+
+<pointer> is a JSON Pointer such as /servers/0/host. An empty pointer (\"\") is the whole
+document and - is the end of an array, so set <file> /servers/- <json> appends.";
+
+/// Runs a non-interactive subcommand. Returns `None` when `args` do not start
+/// with one, so the editor should be started instead, otherwise the exit code.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let rest: Vec<&str> = rest.iter().map(String::as_str).collect();
+    let code = match (command.as_str(), rest.as_slice()) {
+        ("get", [file, pointer]) => get(Path::new(file), pointer),
+        ("set", [file, pointer, json]) => set(Path::new(file), pointer, json),
+        ("delete", [file, pointer]) => delete(Path::new(file), pointer),
+        ("fmt", [file]) => fmt(Path::new(file), false),
+        ("fmt", [file, "-w" | "--write"]) => fmt(Path::new(file), true),
+        ("validate", [file]) => validate(Path::new(file)),
+        ("help" | "-h" | "--help", _) => {
+            println!("{USAGE}");
+            EXIT_OK
+        }
+        ("get" | "set" | "delete" | "fmt" | "validate", _) => {
+            eprintln!("{USAGE}");
+            EXIT_USAGE
+        }
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn get(file: &Path, pointer: &str) -> i32 {
+    let document = match load(file) {
+        Ok(document) => document,
+        Err(code) => return code,
+    };
+    let path = match document.parse_pointer(pointer) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{err}");
+            return EXIT_USAGE;
+        }
+    };
+    match document.get(&path) {
+        Some(value) => print_value(value),
+        None => {
+            eprintln!("{}: nothing at {pointer}", file.display());
+            EXIT_NOT_FOUND
+        }
+    }
+}
+
+fn set(file: &Path, pointer: &str, json: &str) -> i32 {
+    let value: Value = match serde_json::from_str(json) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("invalid json value: {err}");
+            return EXIT_USAGE;
+        }
+    };
+    let mut document = match load(file) {
+        Ok(document) => document,
+        Err(code) => return code,
+    };
+    let path = match document.parse_pointer(pointer) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{err}");
+            return EXIT_USAGE;
+        }
+    };
+    if let Err(err) = document.set(&path, value) {
+        eprintln!("{}: {err}", file.display());
+        return EXIT_NOT_FOUND;
+    }
+    save(&document, file)
+}
+
+fn delete(file: &Path, pointer: &str) -> i32 {
+    let mut document = match load(file) {
+        Ok(document) => document,
+        Err(code) => return code,
+    };
+    let path = match document.parse_pointer(pointer) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{err}");
+            return EXIT_USAGE;
+        }
+    };
+    if let Err(err) = document.remove(&path) {
+        eprintln!("{}: {err}", file.display());
+        return EXIT_NOT_FOUND;
+    }
+    save(&document, file)
+}
+
+/// Prints the file the way the editor would save it, or rewrites it with `write`.
+fn fmt(file: &Path, write: bool) -> i32 {
+    let document = match load(file) {
+        Ok(document) => document,
+        Err(code) => return code,
+    };
+    if write {
+        save(&document, file)
+    } else {
+        print_value(&document.root)
+    }
+}
+
+fn validate(file: &Path) -> i32 {
+    match load(file) {
+        Ok(_) => {
+            println!("{}: valid", file.display());
+            EXIT_OK
+        }
+        Err(code) => code,
+    }
+}
+
+fn load(file: &Path) -> Result<Document, i32> {
+    Document::load(file).map_err(|err| {
+        eprintln!("{}: {err}", file.display());
+        match err {
+            LoadError::Io(_) => EXIT_USAGE,
+            LoadError::Parse(_) => EXIT_NOT_FOUND,
+        }
+    })
+}
+
+fn save(document: &Document, file: &Path) -> i32 {
+    match document.save(file, false) {
+        Ok(()) => EXIT_OK,
+        Err(err) => {
+            eprintln!("could not save {}: {err}", file.display());
+            EXIT_USAGE
+        }
+    }
+}
+
+fn print_value(value: &Value) -> i32 {
+    let mut stdout = io::stdout().lock();
+    match writeln!(stdout, "{}", to_string_pretty(value)) {
+        Ok(()) => EXIT_OK,
+        Err(_) => EXIT_USAGE,
+    }
+}
@@ -0,0 +1,73 @@
+use serde_json::Value;
+
+use crate::document::{JsonPath, PathSegment};
+
+/// How a path differs between the left and the right document.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,   // only in the right document
+    Removed, // only in the left document
+    Changed, // in both, with different values
+}
+
+pub struct Difference {
+    pub path: JsonPath,
+    pub change: Change,
+}
+
+/// Compares two documents by path rather than by text: object members are
+/// matched by key, array items by index. Differences inside objects and arrays
+/// that exist on both sides are reported for their members, anything else for
+/// the whole value. They come in document order, left side first.
+pub fn diff(left: &Value, right: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_values(&mut JsonPath::new(), left, right, &mut differences);
+    differences
+}
+
+fn diff_values(
+    path: &mut JsonPath,
+    left: &Value,
+    right: &Value,
+    differences: &mut Vec<Difference>,
+) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            for (key, left_value) in left {
+                path.push(PathSegment::Key(key.clone()));
+                match right.get(key) {
+                    Some(right_value) => diff_values(path, left_value, right_value, differences),
+                    None => push(differences, path, Change::Removed),
+                }
+                path.pop();
+            }
+            for key in right.keys().filter(|key| !left.contains_key(*key)) {
+                path.push(PathSegment::Key(key.clone()));
+                push(differences, path, Change::Added);
+                path.pop();
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for index in 0..left.len().max(right.len()) {
+                path.push(PathSegment::Index(index));
+                match (left.get(index), right.get(index)) {
+                    (Some(left_value), Some(right_value)) => {
+                        diff_values(path, left_value, right_value, differences)
+                    }
+                    (Some(_), None) => push(differences, path, Change::Removed),
+                    (None, _) => push(differences, path, Change::Added),
+                }
+                path.pop();
+            }
+        }
+        (left, right) if left != right => push(differences, path, Change::Changed),
+        _ => {}
+    }
+}
+
+fn push(differences: &mut Vec<Difference>, path: &JsonPath, change: Change) {
+    differences.push(Difference {
+        path: path.clone(),
+        change,
+    });
+}
@@ -0,0 +1,592 @@
+use std::path::Path;
+use std::{fmt, fs, io};
+
+use serde_json::{Map, Value};
+
+use crate::atomic_write::write_atomic;
+
+/// A single step into the document: either an object key or an array index.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A location in the document, from the root down.
+pub type JsonPath = Vec<PathSegment>;
+
+#[derive(Debug)]
+pub enum DocError {
+    /// Nothing lives at the given path.
+    NotFound(String),
+    /// The parent of the given path is not an object or array.
+    NotAContainer(String),
+    /// An array index past the end of the array.
+    OutOfBounds(String, usize),
+    /// The root itself can not be removed or renamed.
+    Root,
+    /// A non-empty JSON Pointer that does not start with '/'.
+    InvalidPointer(String),
+}
+
+impl fmt::Display for DocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocError::NotFound(path) => write!(f, "nothing at {path}"),
+            DocError::NotAContainer(path) => write!(f, "{path} is not an object or array"),
+            DocError::OutOfBounds(path, len) => {
+                write!(f, "{path} is out of bounds (length {len})")
+            }
+            DocError::Root => write!(f, "the document root can not be changed this way"),
+            DocError::InvalidPointer(pointer) => {
+                write!(f, "{pointer} is not a JSON Pointer, those start with /")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DocError {}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{err}"),
+            LoadError::Parse(err) => write!(
+                f,
+                "invalid json at line {}, column {}: {}",
+                err.line(),
+                err.column(),
+                // serde_json appends the position itself, strip it so it is not repeated
+                err.to_string()
+                    .split(" at line ")
+                    .next()
+                    .unwrap_or_default()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// One visible line of the document, produced by walking the tree depth first.
+pub struct Row<'a> {
+    pub path: JsonPath,
+    pub depth: usize,
+    pub value: &'a Value,
+}
+
+impl Row<'_> {
+    /// The key or index this row is stored under in its parent.
+    pub fn label(&self) -> String {
+        match self.path.last() {
+            Some(PathSegment::Key(key)) => key.clone(),
+            Some(PathSegment::Index(index)) => format!("[{index}]"),
+            None => String::from("(root)"),
+        }
+    }
+}
+
+/// The JSON document being edited. Object keys keep the order they were read
+/// or inserted in, so saving writes them back out the same way.
+pub struct Document {
+    pub root: Value,
+}
+
+impl Document {
+    pub fn new() -> Document {
+        Document {
+            root: Value::Object(Map::new()),
+        }
+    }
+
+    pub fn parse(text: &str) -> serde_json::Result<Document> {
+        Ok(Document {
+            root: serde_json::from_str(text)?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Document, LoadError> {
+        let text = fs::read_to_string(path).map_err(LoadError::Io)?;
+        Document::parse(&text).map_err(LoadError::Parse)
+    }
+
+    pub fn get(&self, path: &[PathSegment]) -> Option<&Value> {
+        get(&self.root, path)
+    }
+
+    pub fn get_mut(&mut self, path: &[PathSegment]) -> Option<&mut Value> {
+        get_mut(&mut self.root, path)
+    }
+
+    /// Sets the value at `path`, replacing whatever was there and creating
+    /// missing intermediate objects. Returns the previous value, if any.
+    pub fn set(&mut self, path: &[PathSegment], value: Value) -> Result<Option<Value>, DocError> {
+        let Some((last, parent_path)) = path.split_last() else {
+            return Ok(Some(std::mem::replace(&mut self.root, value)));
+        };
+
+        let mut parent = &mut self.root;
+        for (depth, segment) in parent_path.iter().enumerate() {
+            parent = match (parent, segment) {
+                (Value::Object(map), PathSegment::Key(key)) => map
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Object(Map::new())),
+                (Value::Array(items), PathSegment::Index(index)) => {
+                    let len = items.len();
+                    items
+                        .get_mut(*index)
+                        .ok_or_else(|| DocError::OutOfBounds(pointer(&path[..=depth]), len))?
+                }
+                _ => return Err(DocError::NotAContainer(pointer(&path[..depth]))),
+            };
+        }
+
+        match (parent, last) {
+            (Value::Object(map), PathSegment::Key(key)) => Ok(map.insert(key.clone(), value)),
+            (Value::Array(items), PathSegment::Index(index)) => {
+                if *index < items.len() {
+                    Ok(Some(std::mem::replace(&mut items[*index], value)))
+                } else if *index == items.len() {
+                    items.push(value);
+                    Ok(None)
+                } else {
+                    Err(DocError::OutOfBounds(pointer(path), items.len()))
+                }
+            }
+            _ => Err(DocError::NotAContainer(pointer(parent_path))),
+        }
+    }
+
+    /// Removes and returns the value at `path`.
+    pub fn remove(&mut self, path: &[PathSegment]) -> Result<Value, DocError> {
+        let Some((last, parent_path)) = path.split_last() else {
+            return Err(DocError::Root);
+        };
+        match (self.get_mut(parent_path), last) {
+            // shift_remove keeps the remaining keys in their original order
+            (Some(Value::Object(map)), PathSegment::Key(key)) => map
+                .shift_remove(key)
+                .ok_or_else(|| DocError::NotFound(pointer(path))),
+            (Some(Value::Array(items)), PathSegment::Index(index)) => {
+                if *index < items.len() {
+                    Ok(items.remove(*index))
+                } else {
+                    Err(DocError::OutOfBounds(pointer(path), items.len()))
+                }
+            }
+            (Some(_), _) => Err(DocError::NotAContainer(pointer(parent_path))),
+            (None, _) => Err(DocError::NotFound(pointer(parent_path))),
+        }
+    }
+
+    /// Renames the key at `path` in place, keeping its position in the object. An
+    /// existing pair already using `new_key` is replaced.
+    pub fn rename(&mut self, path: &[PathSegment], new_key: &str) -> Result<(), DocError> {
+        let Some((PathSegment::Key(old_key), parent_path)) = path.split_last() else {
+            return Err(DocError::Root);
+        };
+        let Some(Value::Object(map)) = self.get_mut(parent_path) else {
+            return Err(DocError::NotAContainer(pointer(parent_path)));
+        };
+        // nothing may change unless the key to rename is there
+        if !map.contains_key(old_key) {
+            return Err(DocError::NotFound(pointer(path)));
+        }
+        if old_key != new_key {
+            map.shift_remove(new_key);
+        }
+        // looked up after the removal above, which may move the old key up by one
+        let index = map
+            .keys()
+            .position(|key| key == old_key)
+            .ok_or_else(|| DocError::NotFound(pointer(path)))?;
+        if let Some((_, value)) = map.shift_remove_entry(old_key) {
+            map.shift_insert(index, new_key.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Sorts the keys of every object in the document alphabetically.
+    pub fn sort_keys(&mut self) {
+        sort_keys(&mut self.root);
+    }
+
+    /// The nodes below the root in depth-first order, only descending into the
+    /// objects and arrays for which `descend` returns true.
+    pub fn rows(&self, descend: &dyn Fn(&JsonPath) -> bool) -> Vec<Row<'_>> {
+        let mut rows = Vec::new();
+        push_children(&mut rows, &self.root, &mut Vec::new(), descend);
+        rows
+    }
+
+    /// The paths of every object and array below the root.
+    pub fn container_paths(&self) -> Vec<JsonPath> {
+        self.rows(&|_| true)
+            .into_iter()
+            .filter(|row| row.value.is_object() || row.value.is_array())
+            .map(|row| row.path)
+            .collect()
+    }
+
+    /// Turns a JSON Pointer into a path into the document, see [`parse_pointer`].
+    pub fn parse_pointer(&self, pointer: &str) -> Result<JsonPath, DocError> {
+        parse_pointer(&self.root, pointer)
+    }
+
+    pub fn to_string_pretty(&self) -> String {
+        to_string_pretty(&self.root)
+    }
+
+    /// Atomically replaces `path` with the document, keeping a `path~` copy of
+    /// the previous contents when `backup` is set.
+    pub fn save(&self, path: &Path, backup: bool) -> io::Result<()> {
+        let mut json_string = self.to_string_pretty();
+        json_string.push('\n');
+        write_atomic(path, json_string.as_bytes(), backup)
+    }
+}
+
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.sort_keys();
+            map.values_mut().for_each(sort_keys);
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// The value at `path` below `root`, if there is one.
+pub fn get<'a>(root: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(root, |value, segment| match (value, segment) {
+            (Value::Object(map), PathSegment::Key(key)) => map.get(key),
+            (Value::Array(items), PathSegment::Index(index)) => items.get(*index),
+            _ => None,
+        })
+}
+
+pub fn get_mut<'a>(root: &'a mut Value, path: &[PathSegment]) -> Option<&'a mut Value> {
+    path.iter()
+        .try_fold(root, |value, segment| match (value, segment) {
+            (Value::Object(map), PathSegment::Key(key)) => map.get_mut(key),
+            (Value::Array(items), PathSegment::Index(index)) => items.get_mut(*index),
+            _ => None,
+        })
+}
+
+/// Turns a JSON Pointer (`/servers/0/host`) into a path below `root`, the empty
+/// pointer being the root. Numeric tokens become array indices wherever `root`
+/// has an array at that point, and `-` is the end of an array, one past the
+/// last item.
+pub fn parse_pointer(root: &Value, pointer: &str) -> Result<JsonPath, DocError> {
+    if pointer.is_empty() {
+        return Ok(JsonPath::new());
+    }
+    let Some(tokens) = pointer.strip_prefix('/') else {
+        return Err(DocError::InvalidPointer(pointer.to_string()));
+    };
+    let mut path = JsonPath::new();
+    for token in tokens.split('/') {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        // RFC 6901 indices are plain digits without leading zeros
+        let index = Some(&token)
+            .filter(|token| token.bytes().all(|byte| byte.is_ascii_digit()))
+            .filter(|token| *token == "0" || !token.starts_with('0'))
+            .and_then(|token| token.parse::<usize>().ok());
+        let segment = match (get(root, &path), index) {
+            (Some(Value::Array(items)), _) if token == "-" => PathSegment::Index(items.len()),
+            (Some(Value::Array(_)), Some(index)) => PathSegment::Index(index),
+            _ => PathSegment::Key(token),
+        };
+        path.push(segment);
+    }
+    Ok(path)
+}
+
+/// The children of an object or array with the segment each is stored under, in
+/// document order. Scalars have none.
+pub fn children(value: &Value) -> Vec<(PathSegment, &Value)> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, child)| (PathSegment::Key(key.clone()), child))
+            .collect(),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, child)| (PathSegment::Index(index), child))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Everything below `value`, which sits at `path`, with their paths in depth-first order.
+pub fn descendants<'a>(value: &'a Value, path: &JsonPath) -> Vec<(JsonPath, &'a Value)> {
+    let mut rows = Vec::new();
+    push_children(&mut rows, value, &mut path.clone(), &|_| true);
+    rows.into_iter().map(|row| (row.path, row.value)).collect()
+}
+
+fn push_children<'a>(
+    rows: &mut Vec<Row<'a>>,
+    value: &'a Value,
+    path: &mut JsonPath,
+    descend: &dyn Fn(&JsonPath) -> bool,
+) {
+    for (segment, child) in children(value) {
+        path.push(segment);
+        rows.push(Row {
+            path: path.clone(),
+            depth: path.len() - 1,
+            value: child,
+        });
+        if descend(path) {
+            push_children(rows, child, path, descend);
+        }
+        path.pop();
+    }
+}
+
+/// The formatting used everywhere json is written out, by the editor and the cli alike.
+pub fn to_string_pretty(value: &Value) -> String {
+    // Serializing a `Value` can not fail.
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+/// Formats a path as a JSON Pointer (RFC 6901).
+pub fn pointer(path: &[PathSegment]) -> String {
+    path.iter()
+        .map(|segment| match segment {
+            PathSegment::Key(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+            PathSegment::Index(index) => format!("/{index}"),
+        })
+        .collect()
+}
+
+/// A short, single line rendering of a value for list views. Objects and arrays
+/// only show how many children they hold.
+pub fn preview(value: &Value) -> String {
+    match value {
+        Value::Object(map) => format!("{{…}} {} keys", map.len()),
+        Value::Array(items) => format!("[…] {} items", items.len()),
+        scalar => scalar.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn document(root: Value) -> Document {
+        Document { root }
+    }
+
+    fn key(key: &str) -> PathSegment {
+        PathSegment::Key(key.to_string())
+    }
+
+    #[test]
+    fn set_updates_and_returns_the_previous_value() {
+        let mut doc = document(json!({"a": 1}));
+        assert_eq!(doc.set(&[key("a")], json!(2)).unwrap(), Some(json!(1)));
+        assert_eq!(doc.root, json!({"a": 2}));
+    }
+
+    #[test]
+    fn set_creates_intermediate_objects() {
+        let mut doc = document(json!({}));
+        assert_eq!(doc.set(&[key("a"), key("b")], json!(true)).unwrap(), None);
+        assert_eq!(doc.root, json!({"a": {"b": true}}));
+    }
+
+    #[test]
+    fn set_appends_one_past_the_end_of_an_array() {
+        let mut doc = document(json!({"list": [1]}));
+        doc.set(&[key("list"), PathSegment::Index(1)], json!(2))
+            .unwrap();
+        assert_eq!(doc.root, json!({"list": [1, 2]}));
+        assert!(matches!(
+            doc.set(&[key("list"), PathSegment::Index(5)], json!(3)),
+            Err(DocError::OutOfBounds(_, 2))
+        ));
+    }
+
+    #[test]
+    fn set_below_a_scalar_fails() {
+        let mut doc = document(json!({"a": 1}));
+        assert!(matches!(
+            doc.set(&[key("a"), key("b")], json!(2)),
+            Err(DocError::NotAContainer(path)) if path == "/a"
+        ));
+        assert_eq!(doc.root, json!({"a": 1}));
+    }
+
+    #[test]
+    fn set_on_the_root_replaces_the_document() {
+        let mut doc = document(json!({"a": 1}));
+        doc.set(&[], json!([1])).unwrap();
+        assert_eq!(doc.root, json!([1]));
+    }
+
+    #[test]
+    fn remove_keeps_the_order_of_the_other_keys() {
+        let mut doc = document(json!({"c": 1, "a": 2, "b": 3}));
+        assert_eq!(doc.remove(&[key("a")]).unwrap(), json!(2));
+        let keys: Vec<&String> = doc.root.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["c", "b"]);
+    }
+
+    #[test]
+    fn sort_keys_sorts_nested_objects_too() {
+        let mut doc = document(json!({"b": [{"z": 1, "y": 2}], "a": {"d": 1, "c": 2}}));
+        doc.sort_keys();
+        assert_eq!(
+            doc.to_string_pretty(),
+            serde_json::to_string_pretty(&json!({"a": {"c": 2, "d": 1}, "b": [{"y": 2, "z": 1}]}))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_from_an_array_shifts_the_rest() {
+        let mut doc = document(json!([1, 2, 3]));
+        assert_eq!(doc.remove(&[PathSegment::Index(0)]).unwrap(), json!(1));
+        assert_eq!(doc.root, json!([2, 3]));
+        assert!(matches!(
+            doc.remove(&[PathSegment::Index(2)]),
+            Err(DocError::OutOfBounds(_, 2))
+        ));
+    }
+
+    #[test]
+    fn remove_errors() {
+        let mut doc = document(json!({"a": 1}));
+        assert!(matches!(doc.remove(&[]), Err(DocError::Root)));
+        assert!(matches!(
+            doc.remove(&[key("b")]),
+            Err(DocError::NotFound(_))
+        ));
+        assert!(matches!(
+            doc.remove(&[key("b"), key("c")]),
+            Err(DocError::NotFound(path)) if path == "/b"
+        ));
+        assert_eq!(doc.root, json!({"a": 1}));
+    }
+
+    #[test]
+    fn rename_keeps_the_position() {
+        let mut doc = document(json!({"a": 1, "b": 2, "c": 3}));
+        doc.rename(&[key("b")], "x").unwrap();
+        let keys: Vec<&String> = doc.root.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["a", "x", "c"]);
+        assert_eq!(doc.root["x"], json!(2));
+    }
+
+    #[test]
+    fn rename_over_an_existing_key_replaces_it() {
+        let mut doc = document(json!({"a": 1, "b": 2, "c": 3}));
+        doc.rename(&[key("c")], "a").unwrap();
+        let keys: Vec<&String> = doc.root.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["b", "a"]);
+        assert_eq!(doc.root["a"], json!(3));
+    }
+
+    #[test]
+    fn rename_of_a_missing_key_changes_nothing() {
+        let mut doc = document(json!({"a": 1, "b": 2}));
+        assert!(matches!(
+            doc.rename(&[key("zzz")], "b"),
+            Err(DocError::NotFound(_))
+        ));
+        assert_eq!(doc.root, json!({"a": 1, "b": 2}));
+    }
+
+    #[test]
+    fn rows_walk_the_tree_depth_first() {
+        let doc = document(json!({"a": {"b": [true]}}));
+        let rows: Vec<(String, usize)> = doc
+            .rows(&|_| true)
+            .iter()
+            .map(|row| (row.label(), row.depth))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (String::from("a"), 0),
+                (String::from("b"), 1),
+                (String::from("[0]"), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_skip_the_children_of_collapsed_nodes() {
+        let doc = document(json!({"a": {"b": 1}, "c": [2]}));
+        let labels: Vec<String> = doc
+            .rows(&|path| path == &vec![key("c")])
+            .iter()
+            .map(Row::label)
+            .collect();
+        assert_eq!(labels, ["a", "c", "[0]"]);
+        assert_eq!(doc.container_paths(), [vec![key("a")], vec![key("c")]]);
+    }
+
+    #[test]
+    fn pointers_escape_slashes_and_tildes() {
+        let path = vec![key("a/b"), key("m~n"), PathSegment::Index(1)];
+        assert_eq!(pointer(&path), "/a~1b/m~0n/1");
+        let doc = document(json!({"a/b": {"m~n": [10, 20]}}));
+        assert_eq!(doc.parse_pointer("/a~1b/m~0n/1").unwrap(), path);
+    }
+
+    #[test]
+    fn parse_pointer_follows_rfc_6901() {
+        let doc = document(json!({"": 0, "a": 1}));
+        assert_eq!(doc.parse_pointer("").unwrap(), JsonPath::new());
+        assert_eq!(doc.parse_pointer("/").unwrap(), vec![key("")]);
+        assert!(matches!(
+            doc.parse_pointer("a/b"),
+            Err(DocError::InvalidPointer(_))
+        ));
+    }
+
+    #[test]
+    fn parse_pointer_indexes_arrays_only() {
+        let doc = document(json!({"list": [1, 2], "map": {}}));
+        assert_eq!(
+            doc.parse_pointer("/list/1").unwrap(),
+            vec![key("list"), PathSegment::Index(1)]
+        );
+        assert_eq!(
+            doc.parse_pointer("/list/-").unwrap(),
+            vec![key("list"), PathSegment::Index(2)]
+        );
+        assert_eq!(
+            doc.parse_pointer("/map/0").unwrap(),
+            vec![key("map"), key("0")]
+        );
+        // leading zeros are not indices
+        assert_eq!(
+            doc.parse_pointer("/list/01").unwrap(),
+            vec![key("list"), key("01")]
+        );
+    }
+
+    #[test]
+    fn appending_through_a_pointer() {
+        let mut doc = document(json!({"list": [1]}));
+        let path = doc.parse_pointer("/list/-").unwrap();
+        doc.set(&path, json!(2)).unwrap();
+        assert_eq!(doc.root, json!({"list": [1, 2]}));
+    }
+}
@@ -0,0 +1,242 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a new file starts out with.
+const NEW_FILE_CONTENTS: &str = "{}\n";
+
+/// Creates `path` holding an empty object, along with any missing parent
+/// directories. Fails if something already exists there.
+pub fn create(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(NEW_FILE_CONTENTS.as_bytes())
+}
+
+/// Renames or moves `from` to `to`, creating missing parent directories. Never
+/// replaces an existing file.
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    refuse_existing(to)?;
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(from, to)
+}
+
+/// Copies the file or directory `from` to `to`. Never replaces an existing file.
+pub fn duplicate(from: &Path, to: &Path) -> io::Result<()> {
+    refuse_existing(to)?;
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    copy_recursively(from, to)
+}
+
+/// `data/a.json` -> `data/a copy.json`, the suggested name for a duplicate.
+pub fn copy_name(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem} copy.{}", extension.to_string_lossy()),
+        None => format!("{stem} copy"),
+    };
+    path.with_file_name(name)
+}
+
+/// Moves `path` into the user's trash instead of deleting it. The trash uses the
+/// freedesktop.org layout (`~/.local/share/Trash`), so file managers can restore
+/// it from there. Returns where the file ended up.
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    let original = fs::canonicalize(path)?;
+    let trash = trash_dir()?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let name = original
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot trash the root"))?;
+    // claim a free name by creating its info file, `a.json`, `a.json.2`, ...
+    for attempt in 1.. {
+        let trashed_name = match attempt {
+            1 => name.clone(),
+            _ => format!("{name}.{attempt}"),
+        };
+        let info_path = info.join(format!("{trashed_name}.trashinfo"));
+        let mut info_file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+
+        // a file left behind without its info file keeps its name
+        let trashed = files.join(&trashed_name);
+        if fs::symlink_metadata(&trashed).is_ok() {
+            drop(info_file);
+            fs::remove_file(&info_path)?;
+            continue;
+        }
+        let result = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode(&original),
+            deletion_date(SystemTime::now())
+        )
+        .and_then(|()| move_path(&original, &trashed));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&info_path);
+            return Err(err);
+        }
+        return Ok(trashed);
+    }
+    unreachable!("ran out of names for the trash")
+}
+
+fn trash_dir() -> io::Result<PathBuf> {
+    if let Some(data) = env::var_os("XDG_DATA_HOME").filter(|data| !data.is_empty()) {
+        return Ok(PathBuf::from(data).join("Trash"));
+    }
+    match env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".local/share/Trash")),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no trash directory, neither XDG_DATA_HOME nor HOME is set",
+        )),
+    }
+}
+
+fn refuse_existing(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// A rename, falling back to copy and delete when `to` is on another file system.
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            copy_recursively(from, to)?;
+            if from.is_dir() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// The path as a URI path, as trash info files store it.
+fn percent_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(byte))
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in UTC.
+fn deletion_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+
+    // days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn copy_names_keep_the_extension() {
+        assert_eq!(
+            copy_name(Path::new("data/a.json")),
+            Path::new("data/a copy.json")
+        );
+        assert_eq!(copy_name(Path::new("data/a")), Path::new("data/a copy"));
+        assert_eq!(copy_name(Path::new("a.tar.gz")), Path::new("a.tar copy.gz"));
+        assert_eq!(copy_name(Path::new(".env")), Path::new(".env copy"));
+    }
+
+    #[test]
+    fn only_unreserved_bytes_stay_unencoded() {
+        assert_eq!(
+            percent_encode(Path::new("/home/me/a-b_c.~1.json")),
+            "/home/me/a-b_c.~1.json"
+        );
+        assert_eq!(
+            percent_encode(Path::new("/tmp/my file%.json")),
+            "/tmp/my%20file%25.json"
+        );
+        assert_eq!(percent_encode(Path::new("/tmp/é")), "/tmp/%C3%A9");
+    }
+
+    #[test]
+    fn deletion_dates_are_civil_dates_in_utc() {
+        let at = |seconds| deletion_date(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(at(0), "1970-01-01T00:00:00");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00");
+        assert_eq!(at(951_868_799), "2000-02-29T23:59:59");
+        assert_eq!(at(1_709_251_199), "2024-02-29T23:59:59");
+        assert_eq!(at(1_735_689_599), "2024-12-31T23:59:59");
+        assert_eq!(at(4_107_542_400), "2100-03-01T00:00:00");
+    }
+}
@@ -0,0 +1,634 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::SystemTime;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use ratatui::widgets::ListState;
+
+/// Extensions the "JSON-like files only" filter keeps.
+const JSON_EXTENSIONS: &[&str] = &[
+    "json", "jsonc", "json5", "jsonl", "ndjson", "geojson", "topojson", "jsonld",
+];
+
+/// Which entries of the tree are shown.
+pub struct TreeFilter {
+    pub show_hidden: bool,  // show dotfiles
+    pub show_ignored: bool, // show entries matched by .gitignore / .ignore files
+    pub json_only: bool,    // only show files with a json-like extension
+    pub name: String,       // only show files whose name contains this, ignoring case
+}
+
+impl TreeFilter {
+    fn shows(&self, node: &FileNode) -> bool {
+        if (!self.show_hidden && node.is_hidden()) || (!self.show_ignored && node.ignored) {
+            return false;
+        }
+        // directories stay so their contents can still be reached, unless a name
+        // is searched for and nothing loaded in them has it
+        if node.is_dir {
+            return self.name.is_empty()
+                || node
+                    .children
+                    .as_ref()
+                    .is_some_and(|children| children.iter().any(|child| self.shows(child)));
+        }
+        if self.json_only && !node.is_json_like() {
+            return false;
+        }
+        self.name.is_empty()
+            || node
+                .name()
+                .to_lowercase()
+                .contains(&self.name.to_lowercase())
+    }
+}
+
+/// How many lines of a highlighted file the preview keeps.
+const PREVIEW_LINES: usize = 200;
+
+/// The start of a file highlighted in the tree, shown in place of the document
+/// so files can be looked at without opening them.
+pub struct FilePreview {
+    pub path: PathBuf,
+    modified: Option<SystemTime>, // reloaded when the file changes
+    pub lines: Vec<String>,
+    pub error: Option<String>, // why the file is not valid json
+}
+
+impl FilePreview {
+    fn load(path: PathBuf, modified: Option<SystemTime>) -> FilePreview {
+        let (lines, error) = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let lines = contents
+                    .lines()
+                    .take(PREVIEW_LINES)
+                    .map(String::from)
+                    .collect();
+                (lines, validate(&path, &contents).err())
+            }
+            Err(err) => (Vec::new(), Some(err.to_string())),
+        };
+        FilePreview {
+            path,
+            modified,
+            lines,
+            error,
+        }
+    }
+}
+
+/// Parses `contents` as json, or as one value per line for json lines files.
+fn validate(path: &Path, contents: &str) -> Result<(), String> {
+    let json_lines = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl") || ext.eq_ignore_ascii_case("ndjson"));
+    if !json_lines {
+        return serde_json::from_str::<serde_json::Value>(contents)
+            .map(|_| ())
+            .map_err(|err| format!("invalid json: {err}"));
+    }
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(err) = serde_json::from_str::<serde_json::Value>(line) {
+            return Err(format!("invalid json on line {}: {err}", number + 1));
+        }
+    }
+    Ok(())
+}
+
+/// A file or directory in the tree panel. Directories read their children the
+/// first time they are expanded, not before.
+pub struct FileNode {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub ignored: bool, // matched by a .gitignore or .ignore file
+    pub expanded: bool,
+    pub children: Option<Vec<FileNode>>,
+}
+
+impl FileNode {
+    fn new(path: PathBuf) -> FileNode {
+        FileNode {
+            is_dir: path.is_dir(),
+            path,
+            ignored: false,
+            expanded: false,
+            children: None,
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.name().starts_with('.')
+    }
+
+    pub fn is_json_like(&self) -> bool {
+        self.path.extension().is_some_and(|ext| {
+            JSON_EXTENSIONS
+                .iter()
+                .any(|json| ext.eq_ignore_ascii_case(json))
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// The state of the `[1] Tree` panel: the loaded part of the directory tree
+/// and which of its visible rows is selected.
+///
+/// The listing is read once and cached. Every loaded directory is watched (not
+/// recursively, so unopened parts of big trees cost nothing) and re-read when
+/// something in it is created, removed or renamed.
+pub struct FileTree {
+    pub root: PathBuf,
+    pub nodes: Vec<FileNode>,
+    pub list_state: ListState,
+    pub page_height: usize, // how many rows fit in the panel, for page up/down
+    pub filter: TreeFilter,
+    ignores: Vec<(PathBuf, Gitignore)>, // the ignore rules of every loaded directory that has any
+    ignores_above: Vec<Gitignore>, // the rules from above the root up to its git repository, closest first
+    watcher: Option<RecommendedWatcher>, // `None` when the platform has no file watching
+    events: Receiver<notify::Result<Event>>,
+    absolute_root: PathBuf, // the watcher reports absolute paths, this maps them back to `root`
+    preview: Option<FilePreview>, // the last previewed file, kept until another one is highlighted
+}
+
+impl FileTree {
+    pub fn new(root: &Path) -> FileTree {
+        let (sender, events) = channel();
+        let mut tree = FileTree {
+            root: root.to_path_buf(),
+            nodes: Vec::new(),
+            list_state: ListState::default().with_selected(Some(0)),
+            page_height: 1,
+            filter: TreeFilter {
+                show_hidden: false,
+                show_ignored: false,
+                json_only: false,
+                name: String::new(),
+            },
+            ignores: Vec::new(),
+            ignores_above: Vec::new(),
+            watcher: recommended_watcher(sender).ok(),
+            events,
+            absolute_root: fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf()),
+            preview: None,
+        };
+        tree.ignores_above = ignores_above(&tree.absolute_root);
+        tree.nodes = tree.read_children(root);
+        tree.watch(root);
+        tree
+    }
+
+    /// Shows `root` instead, with the same filters.
+    pub fn change_root(&mut self, root: &Path) {
+        let mut tree = FileTree::new(root);
+        std::mem::swap(&mut tree.filter, &mut self.filter);
+        tree.page_height = self.page_height;
+        *self = tree;
+    }
+
+    /// Makes the parent of the root the new root, with the old root selected.
+    pub fn go_to_parent(&mut self) {
+        let Some(parent) = self.absolute_root.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        let previous = self.absolute_root.clone();
+        self.change_root(&parent);
+        self.select_path(&previous);
+    }
+
+    /// The preview of the highlighted file, `None` when a directory is highlighted.
+    pub fn preview(&mut self) -> Option<&FilePreview> {
+        let node = self.selected()?;
+        if node.is_dir {
+            return None;
+        }
+        let path = node.path.clone();
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let current = self
+            .preview
+            .as_ref()
+            .is_some_and(|preview| preview.path == path && preview.modified == modified);
+        if !current {
+            self.preview = Some(FilePreview::load(path, modified));
+        }
+        self.preview.as_ref()
+    }
+
+    /// The entries of `dir`, directories first, each group sorted by name. The
+    /// directory's own ignore files are read first so they apply to its entries.
+    fn read_children(&mut self, dir: &Path) -> Vec<FileNode> {
+        self.ignores
+            .retain(|(ignore_dir, _)| !same_path(ignore_dir, dir));
+        if let Some(ignore) = read_ignore_files(dir, [dir.join(".gitignore"), dir.join(".ignore")])
+        {
+            self.ignores.push((normalize(dir), ignore));
+        }
+
+        // an unreadable directory just shows up empty
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut children = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let mut node = FileNode::new(entry.path());
+                node.ignored = self.is_ignored(&node.path, node.is_dir);
+                node
+            })
+            .collect::<Vec<_>>();
+        children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.path.cmp(&b.path)));
+        children
+    }
+
+    /// Checks the ignore rules from the closest directory up, past the root to
+    /// the top of its git repository; the first rule that matches decides, so
+    /// nested files can re-include what a parent ignored.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let path = normalize(path);
+        let within_root = path.ancestors().skip(1).filter_map(|dir| {
+            self.ignores
+                .iter()
+                .find(|(ignore_dir, _)| ignore_dir == dir)
+                .map(|(_, ignore)| ignore.matched(&path, is_dir))
+        });
+        // the rules above the root match against the absolute path
+        let relative = path.strip_prefix(normalize(&self.root)).unwrap_or(&path);
+        let absolute = self.absolute_root.join(relative);
+        let above_root = self
+            .ignores_above
+            .iter()
+            .map(|ignore| ignore.matched(&absolute, is_dir));
+        for matched in within_root.chain(above_root) {
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn watch(&mut self, dir: &Path) {
+        if let Some(watcher) = &mut self.watcher {
+            let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+    }
+
+    /// Applies the file system changes reported since the last call. Returns
+    /// true when the listing changed.
+    pub fn process_events(&mut self) -> bool {
+        let mut dirs = HashSet::new();
+        for event in self.events.try_iter() {
+            let Ok(event) = event else { continue };
+            if event.kind.is_access() {
+                continue;
+            }
+            for path in event.paths {
+                if let Some(parent) = path.parent() {
+                    dirs.insert(self.tree_path(parent));
+                }
+            }
+        }
+        for dir in &dirs {
+            self.refresh_dir(dir);
+        }
+        !dirs.is_empty()
+    }
+
+    /// Turns a path reported by the watcher into the form the tree was built with.
+    fn tree_path(&self, reported: &Path) -> PathBuf {
+        let reported = normalize(reported);
+        match reported.strip_prefix(&self.absolute_root) {
+            Ok(relative) => self.root.join(relative),
+            Err(_) => reported,
+        }
+    }
+
+    /// Re-reads every loaded directory, for when change notifications are unavailable.
+    pub fn refresh(&mut self) {
+        let mut dirs = vec![self.root.clone()];
+        collect_loaded_dirs(&self.nodes, &mut dirs);
+        for dir in dirs {
+            self.refresh_dir(&dir);
+        }
+    }
+
+    /// Re-reads `dir` if it is loaded, keeping the state of entries that are
+    /// still there and the selection on the same path where possible.
+    fn refresh_dir(&mut self, dir: &Path) {
+        let selected = self.selected().map(|node| node.path.clone());
+        let root = self.root.clone();
+        let dir = if same_path(dir, &root) {
+            root
+        } else {
+            match find_mut(&mut self.nodes, dir) {
+                Some(FileNode {
+                    path,
+                    children: Some(_),
+                    ..
+                }) => path.clone(),
+                _ => return,
+            }
+        };
+
+        let mut fresh = self.read_children(&dir);
+        let children = if same_path(&dir, &self.root) {
+            &mut self.nodes
+        } else {
+            match find_mut(&mut self.nodes, &dir).and_then(|node| node.children.as_mut()) {
+                Some(children) => children,
+                None => return,
+            }
+        };
+        std::mem::swap(children, &mut fresh);
+        let mut old = fresh;
+        for node in children.iter_mut() {
+            if let Some(index) = old.iter().position(|old| old.path == node.path) {
+                let previous = old.swap_remove(index);
+                if previous.is_dir == node.is_dir {
+                    node.expanded = previous.expanded;
+                    node.children = previous.children;
+                }
+            }
+        }
+
+        if let Some(selected) = selected {
+            if let Some(index) = self
+                .visible()
+                .iter()
+                .position(|(_, node)| node.path == selected)
+            {
+                self.list_state.select(Some(index));
+                return;
+            }
+        }
+        self.select(self.list_state.selected().unwrap_or(0));
+    }
+
+    /// The rows currently shown, as (depth, node) pairs in display order.
+    pub fn visible(&self) -> Vec<(usize, &FileNode)> {
+        let mut rows = Vec::new();
+        push_visible(&mut rows, &self.nodes, 0, &self.filter);
+        rows
+    }
+
+    pub fn selected(&self) -> Option<&FileNode> {
+        let index = self.list_state.selected()?;
+        self.visible().into_iter().nth(index).map(|(_, node)| node)
+    }
+
+    fn selected_mut(&mut self) -> Option<&mut FileNode> {
+        let mut index = self.list_state.selected()?;
+        nth_visible_mut(&mut self.nodes, &mut index, &self.filter)
+    }
+
+    pub fn select(&mut self, index: usize) {
+        let last = self.visible().len().saturating_sub(1);
+        self.list_state.select(Some(index.min(last)));
+    }
+
+    /// Selects the entry at `path` if it is currently shown.
+    pub fn select_path(&mut self, path: &Path) {
+        let index = self
+            .visible()
+            .iter()
+            .position(|(_, node)| same_path(&node.path, path));
+        if let Some(index) = index {
+            self.list_state.select(Some(index));
+        }
+    }
+
+    pub fn select_previous(&mut self, rows: usize) {
+        let index = self.list_state.selected().unwrap_or(0);
+        self.select(index.saturating_sub(rows));
+    }
+
+    pub fn select_next(&mut self, rows: usize) {
+        let index = self.list_state.selected().unwrap_or(0);
+        self.select(index.saturating_add(rows));
+    }
+
+    /// Expands the selected directory, reading its entries the first time.
+    pub fn expand_selected(&mut self) {
+        let Some(node) = self.selected() else {
+            return;
+        };
+        if !node.is_dir {
+            return;
+        }
+        let dir = node.path.clone();
+        let children = node.children.is_none().then(|| self.read_children(&dir));
+        if let Some(node) = self.selected_mut() {
+            if children.is_some() {
+                node.children = children;
+            }
+            node.expanded = true;
+        }
+        self.watch(&dir);
+    }
+
+    /// Keeps the selection within the rows left after the filter changed.
+    pub fn filter_changed(&mut self) {
+        self.select(self.list_state.selected().unwrap_or(0));
+    }
+
+    pub fn toggle_selected(&mut self) {
+        match self.selected_mut() {
+            Some(node) if node.expanded => node.expanded = false,
+            Some(_) => self.expand_selected(),
+            None => {}
+        }
+    }
+
+    /// Collapses the selected directory, or when it is not an expanded
+    /// directory moves the selection to its parent.
+    pub fn collapse_selected(&mut self) {
+        let Some(node) = self.selected_mut() else {
+            return;
+        };
+        if node.expanded {
+            node.expanded = false;
+            return;
+        }
+        let Some(parent) = node.path.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        if let Some(index) = self
+            .visible()
+            .iter()
+            .position(|(_, node)| node.path == parent)
+        {
+            self.select(index);
+        }
+    }
+}
+
+/// The rules of `files` that exist, relative to `dir`, `None` when there are none.
+fn read_ignore_files(dir: &Path, files: impl IntoIterator<Item = PathBuf>) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut has_rules = false;
+    for file in files {
+        if file.is_file() && builder.add(file).is_none() {
+            has_rules = true;
+        }
+    }
+    has_rules.then(|| builder.build().ok()).flatten()
+}
+
+/// The ignore rules that apply to `root` from outside it: those of every
+/// directory above it up to the top of its git repository, then the
+/// repository's `.git/info/exclude`. Nothing when `root` is not in a repository.
+fn ignores_above(root: &Path) -> Vec<Gitignore> {
+    let Some(repository) = root.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return Vec::new();
+    };
+    let mut ignores: Vec<Gitignore> = root
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(repository))
+        .filter_map(|dir| read_ignore_files(dir, [dir.join(".gitignore"), dir.join(".ignore")]))
+        .collect();
+    ignores.extend(read_ignore_files(
+        repository,
+        [repository.join(".git/info/exclude")],
+    ));
+    ignores
+}
+
+fn collect_loaded_dirs(nodes: &[FileNode], dirs: &mut Vec<PathBuf>) {
+    for node in nodes {
+        if let Some(children) = &node.children {
+            dirs.push(node.path.clone());
+            collect_loaded_dirs(children, dirs);
+        }
+    }
+}
+
+fn find_mut<'a>(nodes: &'a mut [FileNode], path: &Path) -> Option<&'a mut FileNode> {
+    for node in nodes {
+        if same_path(&node.path, path) {
+            return Some(node);
+        }
+        if normalize(path).starts_with(normalize(&node.path)) {
+            return node
+                .children
+                .as_mut()
+                .and_then(|children| find_mut(children, path));
+        }
+    }
+    None
+}
+
+/// Compares paths ignoring `.` components, so `./d` and `d` are the same.
+fn same_path(a: &Path, b: &Path) -> bool {
+    normalize(a) == normalize(b)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+fn push_visible<'a>(
+    rows: &mut Vec<(usize, &'a FileNode)>,
+    nodes: &'a [FileNode],
+    depth: usize,
+    filter: &TreeFilter,
+) {
+    for node in nodes.iter().filter(|node| filter.shows(node)) {
+        rows.push((depth, node));
+        if let (true, Some(children)) = (node.expanded, &node.children) {
+            push_visible(rows, children, depth + 1, filter);
+        }
+    }
+}
+
+/// Finds the `index`th visible node, counting `index` down on the way.
+fn nth_visible_mut<'a>(
+    nodes: &'a mut [FileNode],
+    index: &mut usize,
+    filter: &TreeFilter,
+) -> Option<&'a mut FileNode> {
+    for node in nodes.iter_mut().filter(|node| filter.shows(node)) {
+        if *index == 0 {
+            return Some(node);
+        }
+        *index -= 1;
+        if node.expanded {
+            if let Some(children) = &mut node.children {
+                if let Some(found) = nth_visible_mut(children, index, filter) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(path: &str, children: Option<Vec<FileNode>>) -> FileNode {
+        FileNode {
+            path: PathBuf::from(path),
+            is_dir: children.is_some() || path.ends_with('/'),
+            ignored: false,
+            expanded: true,
+            children,
+        }
+    }
+
+    #[test]
+    fn the_name_filter_keeps_only_directories_with_matches() {
+        let nodes = vec![
+            node(
+                "a",
+                Some(vec![node("a/b", Some(vec![node("a/b/found.json", None)]))]),
+            ),
+            node("c", Some(vec![node("c/other.json", None)])),
+            node("unread/", None),
+            node("Found.txt", None),
+        ];
+        let mut filter = TreeFilter {
+            show_hidden: false,
+            show_ignored: false,
+            json_only: false,
+            name: String::from("found"),
+        };
+        let shown = |filter: &TreeFilter| {
+            let mut rows = Vec::new();
+            push_visible(&mut rows, &nodes, 0, filter);
+            rows.into_iter()
+                .map(|(_, node)| node.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(shown(&filter), ["a", "a/b", "a/b/found.json", "Found.txt"]);
+        filter.json_only = true;
+        assert_eq!(shown(&filter), ["a", "a/b", "a/b/found.json"]);
+        filter.name.clear();
+        assert_eq!(
+            shown(&filter),
+            ["a", "a/b", "a/b/found.json", "c", "c/other.json", "unread/"]
+        );
+    }
+}
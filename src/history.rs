@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// Changes to the same target this close together are undone as one step.
+const COALESCE_WINDOW: Duration = Duration::from_secs(2);
+
+/// A snapshot of the document from before a change, with a description of the
+/// change and the JSON Patch operations that make it.
+struct Entry {
+    label: String,
+    operations: Vec<Value>,
+    document: Value,
+    size: usize,
+    target: Option<String>,
+    at: Instant,
+    state: u64, // names the document as it is after the change
+}
+
+/// Undo/redo stacks of whole document snapshots. The undo stack is bounded by
+/// the approximate memory the snapshots take, dropping the oldest first.
+pub struct History {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    size: usize,
+    max_size: usize,
+    committed: Vec<Value>, // operations of entries dropped off the undo stack, which can no longer be undone
+    states: u64,           // how many document states were named so far
+    base: u64,             // the state before the oldest entry on the undo stack
+    saved: Option<u64>,    // the state last written to disk, `None` once that is gone
+}
+
+impl History {
+    pub fn new(max_size: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            size: 0,
+            max_size,
+            committed: Vec::new(),
+            states: 0,
+            base: 0,
+            saved: Some(0),
+        }
+    }
+
+    /// Records that the document was changed from `before`. When `target` names
+    /// the same thing as the previous change and follows it quickly, the two are
+    /// merged so undo goes back to the state before both.
+    pub fn push(
+        &mut self,
+        label: String,
+        operations: Vec<Value>,
+        before: Value,
+        target: Option<String>,
+    ) {
+        self.redo.clear();
+        let now = Instant::now();
+        let state = self.new_state();
+        if let Some(last) = self.undo.back_mut() {
+            if target.is_some()
+                && last.target == target
+                && now.duration_since(last.at) < COALESCE_WINDOW
+            {
+                last.at = now;
+                last.operations.extend(operations);
+                last.state = state;
+                return;
+            }
+        }
+
+        let size = approximate_size(&before);
+        self.size += size;
+        self.undo.push_back(Entry {
+            label,
+            operations,
+            document: before,
+            size,
+            target,
+            at: now,
+            state,
+        });
+        // always keep the latest entry, however big it is
+        while self.size > self.max_size && self.undo.len() > 1 {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.size -= oldest.size;
+                self.base = oldest.state;
+                self.committed.extend(oldest.operations);
+            }
+        }
+    }
+
+    /// Remembers that the document as it is now was saved. Later changes are
+    /// not merged into the saved one, so undo can always go back to it.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.state());
+        if let Some(last) = self.undo.back_mut() {
+            last.target = None;
+        }
+    }
+
+    /// Forgets the saved state, for when the saved file went away.
+    pub fn forget_saved(&mut self) {
+        self.saved = None;
+    }
+
+    /// Whether undo and redo have brought the document back to how it was saved.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.state())
+    }
+
+    fn state(&self) -> u64 {
+        self.undo.back().map_or(self.base, |entry| entry.state)
+    }
+
+    fn new_state(&mut self) -> u64 {
+        self.states += 1;
+        self.states
+    }
+
+    /// Swaps `current` for the snapshot before the last change. Returns the
+    /// label of the change that was undone.
+    pub fn undo(&mut self, current: &mut Value) -> Option<String> {
+        let mut entry = self.undo.pop_back()?;
+        self.size -= entry.size;
+        // the change now on top was made before the undo, the next one must not join it
+        if let Some(last) = self.undo.back_mut() {
+            last.target = None;
+        }
+        std::mem::swap(current, &mut entry.document);
+        entry.size = approximate_size(&entry.document);
+        let label = entry.label.clone();
+        self.redo.push(entry);
+        Some(label)
+    }
+
+    /// The operations of every change that is not undone, oldest first, making
+    /// up a JSON Patch of the session.
+    pub fn operations(&self) -> Vec<Value> {
+        let recorded = self.undo.iter().flat_map(|entry| &entry.operations);
+        self.committed.iter().chain(recorded).cloned().collect()
+    }
+
+    /// Reapplies the last undone change. Returns its label.
+    pub fn redo(&mut self, current: &mut Value) -> Option<String> {
+        let mut entry = self.redo.pop()?;
+        std::mem::swap(current, &mut entry.document);
+        entry.size = approximate_size(&entry.document);
+        // a redone change never merges with the next one
+        entry.target = None;
+        self.size += entry.size;
+        let label = entry.label.clone();
+        self.undo.push_back(entry);
+        Some(label)
+    }
+}
+
+/// Roughly how many bytes a value occupies in memory.
+fn approximate_size(value: &Value) -> usize {
+    let own = std::mem::size_of::<Value>();
+    match value {
+        Value::String(text) => own + text.len(),
+        Value::Array(items) => own + items.iter().map(approximate_size).sum::<usize>(),
+        Value::Object(map) => {
+            own + map
+                .iter()
+                .map(|(key, child)| key.len() + approximate_size(child))
+                .sum::<usize>()
+        }
+        _ => own,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn edit(history: &mut History, document: &mut Value, value: Value, target: Option<&str>) {
+        let before = std::mem::replace(document, value.clone());
+        history.push(
+            format!("set {value}"),
+            vec![json!({"op": "replace", "path": "", "value": value})],
+            before,
+            target.map(String::from),
+        );
+    }
+
+    #[test]
+    fn undo_and_redo_swap_snapshots() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!(1);
+        edit(&mut history, &mut document, json!(2), None);
+        edit(&mut history, &mut document, json!(3), None);
+        assert_eq!(history.undo(&mut document).as_deref(), Some("set 3"));
+        assert_eq!(document, json!(2));
+        assert_eq!(history.undo(&mut document).as_deref(), Some("set 2"));
+        assert_eq!(history.undo(&mut document), None);
+        assert_eq!(document, json!(1));
+        assert_eq!(history.redo(&mut document).as_deref(), Some("set 2"));
+        assert_eq!(document, json!(2));
+        // a new change drops what could be redone
+        edit(&mut history, &mut document, json!(4), None);
+        assert_eq!(history.redo(&mut document), None);
+        assert_eq!(document, json!(4));
+    }
+
+    #[test]
+    fn quick_changes_to_one_target_undo_together() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!("");
+        edit(&mut history, &mut document, json!("a"), Some("/name"));
+        edit(&mut history, &mut document, json!("ab"), Some("/name"));
+        edit(&mut history, &mut document, json!("abc"), Some("/name"));
+        assert_eq!(history.operations().len(), 3);
+        history.undo(&mut document);
+        assert_eq!(document, json!(""));
+        assert_eq!(history.undo(&mut document), None);
+    }
+
+    #[test]
+    fn other_targets_and_untargeted_changes_stay_apart() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!(0);
+        edit(&mut history, &mut document, json!(1), Some("/a"));
+        edit(&mut history, &mut document, json!(2), Some("/b"));
+        edit(&mut history, &mut document, json!(3), None);
+        edit(&mut history, &mut document, json!(4), None);
+        for expected in [3, 2, 1, 0] {
+            history.undo(&mut document);
+            assert_eq!(document, json!(expected));
+        }
+    }
+
+    #[test]
+    fn changes_after_an_undo_do_not_join_older_ones() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!(0);
+        edit(&mut history, &mut document, json!(1), Some("/a"));
+        edit(&mut history, &mut document, json!(2), Some("/b"));
+        history.undo(&mut document);
+        edit(&mut history, &mut document, json!(3), Some("/a"));
+        history.undo(&mut document);
+        assert_eq!(document, json!(1));
+    }
+
+    #[test]
+    fn redone_changes_do_not_join_the_next_one() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!(0);
+        edit(&mut history, &mut document, json!(1), Some("/a"));
+        history.undo(&mut document);
+        history.redo(&mut document);
+        edit(&mut history, &mut document, json!(2), Some("/a"));
+        history.undo(&mut document);
+        assert_eq!(document, json!(1));
+    }
+
+    #[test]
+    fn undoing_back_to_the_saved_state_is_saved() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!(0);
+        assert!(history.is_saved());
+        edit(&mut history, &mut document, json!(1), Some("/a"));
+        history.mark_saved();
+        edit(&mut history, &mut document, json!(2), Some("/a"));
+        assert!(!history.is_saved());
+        history.undo(&mut document);
+        assert!(history.is_saved());
+        history.undo(&mut document);
+        assert!(!history.is_saved());
+        history.redo(&mut document);
+        assert!(history.is_saved());
+        // a different change from the saved state is not saved
+        history.undo(&mut document);
+        edit(&mut history, &mut document, json!(3), None);
+        assert!(!history.is_saved());
+        history.forget_saved();
+        history.undo(&mut document);
+        assert!(!history.is_saved());
+    }
+
+    #[test]
+    fn changes_after_a_save_do_not_join_the_saved_one() {
+        let mut history = History::new(usize::MAX);
+        let mut document = json!("");
+        edit(&mut history, &mut document, json!("a"), Some("/name"));
+        history.mark_saved();
+        edit(&mut history, &mut document, json!("ab"), Some("/name"));
+        assert!(!history.is_saved());
+        history.undo(&mut document);
+        assert_eq!(document, json!("a"));
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn the_oldest_snapshots_are_dropped_past_the_memory_bound() {
+        let big = json!("x".repeat(1000));
+        let mut history = History::new(2500);
+        let mut document = big.clone();
+        for value in 1..=5 {
+            edit(&mut history, &mut document, json!(value), None);
+            edit(&mut history, &mut document, big.clone(), None);
+        }
+        assert!(history.size <= 2500);
+        let mut undone = 0;
+        while history.undo(&mut document).is_some() {
+            undone += 1;
+        }
+        assert!(undone < 10, "{undone} changes kept");
+        assert!(undone >= 1);
+        // the latest change is kept however big it is
+        let mut history = History::new(1);
+        edit(&mut history, &mut document, big.clone(), None);
+        edit(&mut history, &mut document, json!(1), None);
+        assert_eq!(history.undo(&mut document).as_deref(), Some("set 1"));
+        assert_eq!(history.undo(&mut document), None);
+    }
+
+    #[test]
+    fn dropped_changes_stay_in_the_session_patch() {
+        let mut history = History::new(1);
+        let mut document = json!(0);
+        for value in 1..=3 {
+            edit(&mut history, &mut document, json!(value), None);
+        }
+        let values: Vec<Value> = history
+            .operations()
+            .iter()
+            .map(|operation| operation["value"].clone())
+            .collect();
+        assert_eq!(values, [json!(1), json!(2), json!(3)]);
+        // undone changes are not part of it
+        history.undo(&mut document);
+        assert_eq!(history.operations().len(), 2);
+    }
+}
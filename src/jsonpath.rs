@@ -0,0 +1,289 @@
+use serde_json::Value;
+
+use crate::document::{children, descendants, get, JsonPath, PathSegment};
+
+/// What one step of a JSONPath picks from a value.
+enum Selector {
+    Name(String),
+    Index(i64), // negative counts from the end
+    Slice(Option<i64>, Option<i64>),
+    Wildcard,
+}
+
+struct Step {
+    descendants: bool, // `..`, applies to the value and everything below it
+    selectors: Vec<Selector>,
+}
+
+/// The paths of everything a JSONPath expression such as `$.servers[*].host`
+/// matches, in document order. Supports `.name`, `['name']`, `[0]`, `[-1]`,
+/// `[1:3]`, `*`, unions like `[0,2]` and recursive descent with `..`.
+pub fn select(root: &Value, expression: &str) -> Result<Vec<JsonPath>, String> {
+    let steps = parse(expression)?;
+    let mut paths = vec![JsonPath::new()];
+    for step in &steps {
+        let mut next = Vec::new();
+        for path in &paths {
+            let mut starts = vec![path.clone()];
+            if step.descendants {
+                let below = descendants(value_at(root, path), path);
+                starts.extend(below.into_iter().map(|(path, _)| path));
+            }
+            for start in starts {
+                let value = value_at(root, &start);
+                for selector in &step.selectors {
+                    push_selected(value, &start, selector, &mut next);
+                }
+            }
+        }
+        paths = next;
+    }
+    Ok(paths)
+}
+
+fn value_at<'a>(root: &'a Value, path: &[PathSegment]) -> &'a Value {
+    get(root, path).unwrap_or(&Value::Null)
+}
+
+fn push_selected(value: &Value, path: &JsonPath, selector: &Selector, paths: &mut Vec<JsonPath>) {
+    let mut push = |segment: PathSegment| {
+        let mut child = path.clone();
+        child.push(segment);
+        paths.push(child);
+    };
+    match (selector, value) {
+        (Selector::Wildcard, value) => {
+            for (segment, _) in children(value) {
+                push(segment);
+            }
+        }
+        (Selector::Name(name), Value::Object(map)) if map.contains_key(name) => {
+            push(PathSegment::Key(name.clone()))
+        }
+        (Selector::Index(index), Value::Array(items)) => {
+            if let Some(index) = resolve_index(*index, items.len()) {
+                push(PathSegment::Index(index));
+            }
+        }
+        (Selector::Slice(start, end), Value::Array(items)) => {
+            let len = items.len() as i64;
+            let clamp = |bound: i64| {
+                if bound < 0 {
+                    (len + bound).max(0)
+                } else {
+                    bound.min(len)
+                }
+            };
+            let start = start.map_or(0, clamp);
+            let end = end.map_or(len, clamp);
+            for index in start..end {
+                push(PathSegment::Index(index as usize));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    usize::try_from(index).ok().filter(|index| *index < len)
+}
+
+fn parse(expression: &str) -> Result<Vec<Step>, String> {
+    let mut rest = expression
+        .trim()
+        .strip_prefix('$')
+        .ok_or("a JSONPath starts with $")?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        let descendants = rest.starts_with("..");
+        if descendants {
+            rest = &rest[2..];
+        } else if let Some(after_dot) = rest.strip_prefix('.') {
+            rest = after_dot;
+        } else if !rest.starts_with('[') {
+            return Err(format!("unexpected `{rest}`"));
+        }
+
+        let selectors = if let Some(inside) = rest.strip_prefix('[') {
+            let end = closing_bracket(inside).ok_or("missing ]")?;
+            let selectors = parse_bracket(&inside[..end])?;
+            rest = &inside[end + 1..];
+            selectors
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            match name {
+                "" => return Err(String::from("missing name after .")),
+                "*" => vec![Selector::Wildcard],
+                name => vec![Selector::Name(name.to_string())],
+            }
+        };
+        steps.push(Step {
+            descendants,
+            selectors,
+        });
+    }
+    Ok(steps)
+}
+
+/// Where the `]` closing a bracket is, skipping over quoted names.
+fn closing_bracket(inside: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, character) in inside.char_indices() {
+        match (quote, character) {
+            (None, '\'' | '"') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, ']') => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The members of a union like `0, 'a,b'`, split on the commas outside quoted names.
+fn union_members(inside: &str) -> Vec<&str> {
+    let mut members = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (index, character) in inside.char_indices() {
+        match (quote, character) {
+            (None, '\'' | '"') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, ',') => {
+                members.push(&inside[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    members.push(&inside[start..]);
+    members
+}
+
+fn parse_bracket(inside: &str) -> Result<Vec<Selector>, String> {
+    union_members(inside)
+        .into_iter()
+        .map(|part| {
+            let part = part.trim();
+            let quoted = part.len() >= 2
+                && ((part.starts_with('\'') && part.ends_with('\''))
+                    || (part.starts_with('"') && part.ends_with('"')));
+            if quoted {
+                return Ok(Selector::Name(part[1..part.len() - 1].to_string()));
+            }
+            if part == "*" {
+                return Ok(Selector::Wildcard);
+            }
+            let number = |text: &str| -> Result<Option<i64>, String> {
+                match text.trim() {
+                    "" => Ok(None),
+                    text => text
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| format!("`{text}` is not an index")),
+                }
+            };
+            match part.split_once(':') {
+                Some((start, end)) => Ok(Selector::Slice(number(start)?, number(end)?)),
+                None => number(part)?
+                    .map(Selector::Index)
+                    .ok_or_else(|| String::from("empty []")),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::document::pointer;
+
+    fn pointers(root: &Value, expression: &str) -> Vec<String> {
+        select(root, expression)
+            .unwrap()
+            .iter()
+            .map(|path| pointer(path))
+            .collect()
+    }
+
+    fn servers() -> Value {
+        json!({
+            "servers": [
+                {"host": "a", "port": 80},
+                {"host": "b", "port": 443},
+                {"host": "c", "tags": {"host": "nested"}}
+            ],
+            "a,b": 1,
+            "name": "prod"
+        })
+    }
+
+    #[test]
+    fn names_indices_and_wildcards() {
+        let root = servers();
+        assert_eq!(pointers(&root, "$"), [""]);
+        assert_eq!(pointers(&root, "$.name"), ["/name"]);
+        assert_eq!(pointers(&root, "$.servers[1].host"), ["/servers/1/host"]);
+        assert_eq!(pointers(&root, "$['name']"), ["/name"]);
+        assert_eq!(
+            pointers(&root, "$.servers[*].port"),
+            ["/servers/0/port", "/servers/1/port"]
+        );
+        assert!(pointers(&root, "$.missing").is_empty());
+    }
+
+    #[test]
+    fn negative_indices_and_slices() {
+        let root = json!([0, 1, 2, 3, 4]);
+        assert_eq!(pointers(&root, "$[-1]"), ["/4"]);
+        assert!(pointers(&root, "$[-6]").is_empty());
+        assert_eq!(pointers(&root, "$[1:3]"), ["/1", "/2"]);
+        assert_eq!(pointers(&root, "$[-2:]"), ["/3", "/4"]);
+        assert_eq!(pointers(&root, "$[:-3]"), ["/0", "/1"]);
+        assert!(pointers(&root, "$[3:1]").is_empty());
+    }
+
+    #[test]
+    fn recursive_descent() {
+        let root = servers();
+        assert_eq!(
+            pointers(&root, "$..host"),
+            [
+                "/servers/0/host",
+                "/servers/1/host",
+                "/servers/2/host",
+                "/servers/2/tags/host"
+            ]
+        );
+        assert_eq!(pointers(&root, "$.servers..tags"), ["/servers/2/tags"]);
+    }
+
+    #[test]
+    fn unions() {
+        let root = servers();
+        assert_eq!(
+            pointers(&root, "$.servers[0,2].host"),
+            ["/servers/0/host", "/servers/2/host"]
+        );
+        assert_eq!(pointers(&root, "$['name', \"a,b\"]"), ["/name", "/a,b"]);
+        // a comma inside a quoted name does not split the union
+        assert_eq!(pointers(&root, "$['a,b']"), ["/a,b"]);
+        assert_eq!(pointers(&root, "$['x]y', 'name']"), ["/name"]);
+    }
+
+    #[test]
+    fn errors() {
+        let root = servers();
+        let error = |expression: &str| select(&root, expression).err().unwrap();
+        assert_eq!(error("servers"), "a JSONPath starts with $");
+        assert_eq!(error("$.servers[0"), "missing ]");
+        assert_eq!(error("$."), "missing name after .");
+        assert_eq!(error("$[x]"), "`x` is not an index");
+        assert_eq!(error("$[]"), "empty []");
+        assert_eq!(error("$name"), "unexpected `name`");
+    }
+}
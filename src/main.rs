@@ -1,6 +1,12 @@
-use std::{error::Error, io};
+use std::{
+    env,
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-use app::CurrentlyDeleting;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     crossterm::{
@@ -11,17 +17,36 @@ use ratatui::{
         execute,
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     },
+    layout::Direction,
     Terminal,
 };
 
 mod app;
+mod atomic_write;
+mod cli;
+mod clipboard;
+mod diff;
+mod document;
+mod file_ops;
+mod file_tree;
+mod history;
+mod jsonpath;
+mod patch;
+mod query;
+mod search;
 mod ui;
 use crate::{
-    app::{App, CurrentScreen, CurrentlyEditing},
+    app::{App, CurrentScreen, CurrentlyEditing, FileOperation, FileSave, PatchAction},
     ui::ui,
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // subcommands such as `get` and `fmt` run without the terminal ui
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        process::exit(code);
+    }
+
     // setup terminal
     enable_raw_mode()?;
     let mut stderr = io::stderr(); // This is a special case. Normally using stdout is fine
@@ -31,6 +56,25 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // create app and run it
     let mut app = App::new();
+    let mut args = args.into_iter();
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // save somewhere other than the file that was opened
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            "-b" | "--backup" => app.backup = true,
+            // show another directory in the tree panel
+            "-r" | "--root" => {
+                if let Some(root) = args.next() {
+                    app.change_root(Path::new(&root));
+                }
+            }
+            path => app.open_file(Path::new(path)),
+        }
+    }
+    if output.is_some() {
+        app.file_path = output;
+    }
     let res = run_app(&mut terminal, &mut app);
     // restore terminal
     disable_raw_mode()?;
@@ -42,11 +86,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     terminal.show_cursor()?;
 
-    if let Ok(do_print) = res {
-        if do_print {
-            app.print_json()?;
-        }
-    } else if let Err(err) = res {
+    if let Err(err) = res {
         println!("{err:?}");
     }
 
@@ -54,48 +94,253 @@ fn main() -> Result<(), Box<dyn Error>> {
 } // added comment 2
   //
   // added a comment
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<bool> {
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
     loop {
+        if app.diff.is_some() {
+            app.refresh_diff();
+        }
         terminal.draw(|f| ui(f, app))?;
 
+        // wait for a key, picking up changes to the files in the tree panel meanwhile
+        while !event::poll(Duration::from_millis(250))? {
+            if app.file_tree.process_events() {
+                terminal.draw(|f| ui(f, app))?;
+            }
+        }
         if let Event::Key(key) = event::read()? {
             if key.kind == event::KeyEventKind::Release {
                 // Skip events that are not KeyEventKind::Press
                 continue;
             }
+            // status messages stay up until the next key press
+            app.status_message = None;
             match app.current_screen {
+                // Second key of a two key command
+                CurrentScreen::Main if app.pending_key.is_some() => {
+                    match (app.pending_key.take(), key.code) {
+                        (Some('z'), KeyCode::Char(digit)) => {
+                            if let Some(depth) = digit.to_digit(10) {
+                                app.expand_to_depth(depth as usize);
+                            }
+                        }
+                        (Some('g'), KeyCode::Char('g')) => app.select(0),
+                        (Some('g'), KeyCode::Char('t')) => app.next_tab(),
+                        (Some('g'), KeyCode::Char('T')) => app.previous_tab(),
+                        (Some('p'), KeyCode::Char('e')) => {
+                            app.start_patch_action(PatchAction::Export)
+                        }
+                        (Some('p'), KeyCode::Char('a')) => {
+                            app.start_patch_action(PatchAction::Apply)
+                        }
+                        (Some('p'), KeyCode::Char('m')) => {
+                            app.start_patch_action(PatchAction::ApplyMerge)
+                        }
+                        (Some('p'), KeyCode::Char('g')) => {
+                            app.start_patch_action(PatchAction::ExportMerge)
+                        }
+                        _ => {}
+                    }
+                }
                 // Main Screen Inputs
                 CurrentScreen::Main => match (key.code, key.modifiers) {
-                    (KeyCode::Char('e'), KeyModifiers::NONE) => {
+                    (KeyCode::Char('e'), KeyModifiers::NONE) | (KeyCode::Enter, _)
+                        if app.start_editing_selected() =>
+                    {
                         app.current_screen = CurrentScreen::Editing;
-                        app.currently_editing = Some(CurrentlyEditing::Key);
                     }
-                    (KeyCode::Char('q'), KeyModifiers::NONE) => {
-                        app.current_screen = CurrentScreen::Exiting;
+                    (KeyCode::Char('a'), KeyModifiers::NONE)
+                    | (KeyCode::Char('e'), KeyModifiers::NONE) => {
+                        app.current_screen = CurrentScreen::Editing;
+                        app.currently_editing = Some(CurrentlyEditing::Key);
                     }
-                    (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                    (KeyCode::Char('q'), KeyModifiers::NONE)
+                    | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        // only ask when there is something to lose
+                        if !app.has_unsaved_changes() {
+                            return Ok(());
+                        }
                         app.current_screen = CurrentScreen::Exiting;
                     }
-                    (KeyCode::Char('d'), KeyModifiers::NONE) => {
+                    (KeyCode::Char('d'), KeyModifiers::NONE) | (KeyCode::Delete, _)
+                        if app.selected_path().is_some() =>
+                    {
                         app.current_screen = CurrentScreen::Deleting;
-                        app.currently_deleting = Some(CurrentlyDeleting::Index);
+                    }
+                    (KeyCode::Up, _) | (KeyCode::Char('k'), KeyModifiers::NONE) => {
+                        app.select_previous(1);
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Char('j'), KeyModifiers::NONE) => {
+                        app.select_next(1);
+                    }
+                    (KeyCode::PageUp, _) => {
+                        app.select_previous(app.json_page_height);
+                    }
+                    (KeyCode::PageDown, _) => {
+                        app.select_next(app.json_page_height);
+                    }
+                    (KeyCode::Home, _) => {
+                        app.select(0);
+                    }
+                    (KeyCode::Char('g'), KeyModifiers::NONE) => {
+                        app.pending_key = Some('g');
+                    }
+                    (KeyCode::Char('p'), KeyModifiers::NONE) => {
+                        app.pending_key = Some('p');
+                    }
+                    (KeyCode::Char(':'), _) => {
+                        app.current_screen = CurrentScreen::GoingTo;
+                    }
+                    (KeyCode::Char('/'), _) => {
+                        app.start_search();
+                    }
+                    (KeyCode::Char('R'), _) => {
+                        app.start_replace();
+                    }
+                    (KeyCode::Char('f'), KeyModifiers::NONE) => {
+                        app.start_query();
+                    }
+                    (KeyCode::Esc, _) => {
+                        app.matches.clear();
+                    }
+                    (KeyCode::Char('n'), KeyModifiers::NONE) => {
+                        app.next_match();
+                    }
+                    (KeyCode::Char('N'), _) => {
+                        app.previous_match();
+                    }
+                    (KeyCode::Char('y'), KeyModifiers::NONE) => {
+                        app.copy_selected_pointer();
+                    }
+                    (KeyCode::Char('?'), _) => {
+                        app.help_scroll = 0;
+                        app.current_screen = CurrentScreen::Help;
+                    }
+                    // Alt+1 to Alt+9 switch to that tab
+                    (KeyCode::Char(digit @ '1'..='9'), KeyModifiers::ALT) => {
+                        app.switch_tab(digit as usize - '1' as usize);
+                    }
+                    (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                        app.new_tab();
+                    }
+                    (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                        app.close_tab();
+                    }
+                    (KeyCode::Char('|'), _) => {
+                        app.toggle_split(Direction::Horizontal);
+                    }
+                    (KeyCode::Char('-'), KeyModifiers::NONE) => {
+                        app.toggle_split(Direction::Vertical);
+                    }
+                    (KeyCode::Tab, _) => {
+                        app.focus_other_pane();
+                    }
+                    (KeyCode::Char('>'), _) => {
+                        app.copy_to_other_pane();
+                    }
+                    (KeyCode::Char('D'), _) => {
+                        app.toggle_diff();
+                    }
+                    (KeyCode::Char(']'), _) => {
+                        app.next_difference();
+                    }
+                    (KeyCode::Char('['), _) => {
+                        app.previous_difference();
+                    }
+                    (KeyCode::Char('H'), _) => {
+                        app.take_side(true);
+                    }
+                    (KeyCode::Char('L'), _) => {
+                        app.take_side(false);
+                    }
+                    (KeyCode::End, _) | (KeyCode::Char('G'), _) => {
+                        app.select(usize::MAX);
                     }
                     (KeyCode::Char('1'), KeyModifiers::NONE) => {
                         app.current_screen = CurrentScreen::FileTree;
                     }
+                    (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                        if app.file_path.is_some() {
+                            app.save();
+                        } else {
+                            app.current_screen = CurrentScreen::SavingAs;
+                        }
+                    }
+                    (KeyCode::Char('S'), _) => {
+                        app.current_screen = CurrentScreen::SavingAs;
+                    }
+                    (KeyCode::Char('s'), KeyModifiers::NONE) => {
+                        app.sort_keys();
+                    }
+                    (KeyCode::Right, _) | (KeyCode::Char('l'), KeyModifiers::NONE) => {
+                        app.expand_selected();
+                    }
+                    (KeyCode::Left, _) | (KeyCode::Char('h'), KeyModifiers::NONE) => {
+                        app.collapse_selected();
+                    }
+                    (KeyCode::Char(' '), _) => {
+                        app.toggle_expanded();
+                    }
+                    (KeyCode::Char('E'), _) => {
+                        app.expand_all();
+                    }
+                    (KeyCode::Char('C'), _) => {
+                        app.collapse_all();
+                    }
+                    (KeyCode::Char('z'), KeyModifiers::NONE) => {
+                        app.pending_key = Some('z');
+                    }
+                    (KeyCode::Char('u'), KeyModifiers::NONE)
+                    | (KeyCode::Char('z'), KeyModifiers::CONTROL) => {
+                        app.undo();
+                    }
+                    (KeyCode::Char('r'), KeyModifiers::CONTROL)
+                    | (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
+                        app.redo();
+                    }
                     _ => {}
                 },
                 // Exiting inputs
                 CurrentScreen::Exiting => match (key.code, key.modifiers) {
-                    (KeyCode::Char('y'), KeyModifiers::NONE) => {
-                        return Ok(true);
+                    // Save
+                    (KeyCode::Char('s'), KeyModifiers::NONE)
+                    | (KeyCode::Char('y'), KeyModifiers::NONE) => {
+                        if app.save_all() {
+                            return Ok(());
+                        } else if app.file_path.is_none() {
+                            // save_all stopped at a tab that needs a name first
+                            app.quit_after_save = true;
+                            app.current_screen = CurrentScreen::SavingAs;
+                        } else {
+                            // stay in the editor so the error can be read
+                            app.current_screen = CurrentScreen::Main;
+                        }
+                    }
+                    // Discard
+                    (KeyCode::Char('d'), KeyModifiers::NONE)
+                    | (KeyCode::Char('n'), KeyModifiers::NONE) => {
+                        return Ok(());
                     }
-                    (KeyCode::Char('n'), KeyModifiers::NONE)
-                    | (KeyCode::Char('q'), KeyModifiers::NONE) => {
-                        return Ok(false);
+                    // Cancel
+                    (KeyCode::Char('c'), _) | (KeyCode::Esc, _) | (KeyCode::Char('q'), _) => {
+                        app.current_screen = CurrentScreen::Main;
                     }
-                    (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                        return Ok(false);
+                    _ => {}
+                },
+                // Save As Inputs
+                CurrentScreen::SavingAs if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter if finish_save_as(app) => {
+                        return Ok(());
+                    }
+                    KeyCode::Backspace => {
+                        app.save_as_input.pop();
+                    }
+                    KeyCode::Esc => {
+                        app.current_screen = CurrentScreen::Main;
+                        app.quit_after_save = false;
+                        app.save_as_input = String::new();
+                    }
+                    KeyCode::Char(value) => {
+                        app.save_as_input.push(value);
                     }
                     _ => {}
                 },
@@ -108,8 +353,9 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                                     app.currently_editing = Some(CurrentlyEditing::Value);
                                 }
                                 CurrentlyEditing::Value => {
-                                    app.save_key_value();
-                                    app.current_screen = CurrentScreen::Main;
+                                    if app.save_key_value() {
+                                        app.current_screen = CurrentScreen::Main;
+                                    }
                                 }
                             }
                         }
@@ -128,11 +374,14 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     }
                     KeyCode::Esc => {
                         app.current_screen = CurrentScreen::Main;
-                        app.currently_editing = None;
+                        app.cancel_editing();
                     }
                     KeyCode::Tab => {
                         app.toggle_editing();
                     }
+                    KeyCode::Char('t') if key.modifiers == KeyModifiers::CONTROL => {
+                        app.value_as_string = !app.value_as_string;
+                    }
                     KeyCode::Char(value) => {
                         if let Some(editing) = &app.currently_editing {
                             match editing {
@@ -147,45 +396,63 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     }
                     _ => {}
                 },
-                // Deleting Inputs
-                CurrentScreen::Deleting if key.kind == KeyEventKind::Press => match key.code {
-                    KeyCode::Enter => {
-                        if let Some(deleting) = &app.currently_deleting {
-                            match deleting {
-                                CurrentlyDeleting::Index => {
-                                    app.currently_deleting = Some(CurrentlyDeleting::Index);
-                                    app.delete_key();
-                                    app.delete_index = String::new();
-                                    app.current_screen = CurrentScreen::Main;
-                                }
-                            }
-                        }
+                // Overwrite confirmation, shown on top of the editing popup
+                CurrentScreen::ConfirmingOverwrite => match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => {
+                        app.overwrite_confirmed = true;
+                        app.current_screen = if app.save_key_value() {
+                            CurrentScreen::Main
+                        } else {
+                            CurrentScreen::Editing
+                        };
                     }
-                    // Backspace
-                    KeyCode::Backspace => {
-                        if let Some(deleting) = &app.currently_deleting {
-                            match deleting {
-                                CurrentlyDeleting::Index => {
-                                    app.delete_index.pop();
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        app.current_screen = CurrentScreen::Editing;
+                    }
+                    _ => {}
+                },
+                // Replacing an existing file from one of the save popups
+                CurrentScreen::ConfirmingFileOverwrite => match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => {
+                        app.file_overwrite_confirmed = true;
+                        match app.pending_file_save.take() {
+                            Some((FileSave::SaveAs, _)) => {
+                                app.current_screen = CurrentScreen::SavingAs;
+                                if finish_save_as(app) {
+                                    return Ok(());
                                 }
                             }
+                            Some((FileSave::Patch, _)) => {
+                                app.current_screen = CurrentScreen::Patching;
+                                app.finish_patch_action();
+                            }
+                            Some((FileSave::QueryResult, _)) => {
+                                app.current_screen = CurrentScreen::SavingQuery;
+                                app.save_query_result();
+                            }
+                            None => app.current_screen = CurrentScreen::Main,
                         }
+                        app.file_overwrite_confirmed = false;
                     }
-                    // Escape
-                    KeyCode::Esc => {
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        app.current_screen = match app.pending_file_save.take() {
+                            Some((FileSave::SaveAs, _)) => CurrentScreen::SavingAs,
+                            Some((FileSave::Patch, _)) => CurrentScreen::Patching,
+                            Some((FileSave::QueryResult, _)) => CurrentScreen::SavingQuery,
+                            None => CurrentScreen::Main,
+                        };
+                    }
+                    _ => {}
+                },
+                // Deleting Inputs
+                CurrentScreen::Deleting if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter | KeyCode::Char('y') => {
+                        app.delete_key();
                         app.current_screen = CurrentScreen::Main;
-                        app.currently_editing = None;
-                        app.delete_index = String::new();
                     }
-                    // Any Character
-                    KeyCode::Char(value) => {
-                        if let Some(deleting) = &app.currently_deleting {
-                            match deleting {
-                                CurrentlyDeleting::Index => {
-                                    app.delete_index.push(value);
-                                }
-                            }
-                        }
+                    // Escape
+                    KeyCode::Esc | KeyCode::Char('n') => {
+                        app.current_screen = CurrentScreen::Main;
                     }
                     _ => {}
                 },
@@ -195,14 +462,362 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                         (KeyCode::Char('2'), KeyModifiers::NONE) => {
                             app.current_screen = CurrentScreen::Main;
                         }
+                        (KeyCode::Up, _) | (KeyCode::Char('k'), KeyModifiers::NONE) => {
+                            app.file_tree.select_previous(1);
+                        }
+                        (KeyCode::Down, _) | (KeyCode::Char('j'), KeyModifiers::NONE) => {
+                            app.file_tree.select_next(1);
+                        }
+                        (KeyCode::PageUp, _) => {
+                            app.file_tree.select_previous(app.file_tree.page_height);
+                        }
+                        (KeyCode::PageDown, _) => {
+                            app.file_tree.select_next(app.file_tree.page_height);
+                        }
+                        (KeyCode::Home, _) | (KeyCode::Char('g'), KeyModifiers::NONE) => {
+                            app.file_tree.select(0);
+                        }
+                        (KeyCode::End, _) | (KeyCode::Char('G'), _) => {
+                            app.file_tree.select(usize::MAX);
+                        }
+                        (KeyCode::Right, _) | (KeyCode::Char('l'), KeyModifiers::NONE) => {
+                            app.file_tree.expand_selected();
+                        }
+                        (KeyCode::Char('r'), KeyModifiers::NONE) | (KeyCode::F(5), _) => {
+                            app.file_tree.refresh();
+                            app.status_message = Some(String::from("refreshed file tree"));
+                        }
+                        (KeyCode::Left, _) | (KeyCode::Char('h'), KeyModifiers::NONE) => {
+                            app.file_tree.collapse_selected();
+                        }
+                        (KeyCode::Enter, _) if app.open_selected_tree_entry() => {
+                            app.current_screen = CurrentScreen::Main;
+                        }
+                        (KeyCode::Char('.'), KeyModifiers::NONE) => {
+                            app.file_tree.filter.show_hidden = !app.file_tree.filter.show_hidden;
+                            app.file_tree.filter_changed();
+                        }
+                        (KeyCode::Char('I'), _) => {
+                            app.file_tree.filter.show_ignored = !app.file_tree.filter.show_ignored;
+                            app.file_tree.filter_changed();
+                        }
+                        (KeyCode::Char('J'), _) => {
+                            app.file_tree.filter.json_only = !app.file_tree.filter.json_only;
+                            app.file_tree.filter_changed();
+                        }
+                        (KeyCode::Char('R'), _) => {
+                            app.change_root_to_selected();
+                        }
+                        (KeyCode::Char('-'), KeyModifiers::NONE) | (KeyCode::Backspace, _) => {
+                            app.file_tree.go_to_parent();
+                        }
+                        (KeyCode::Char('/'), KeyModifiers::NONE) => {
+                            app.current_screen = CurrentScreen::FilteringTree;
+                        }
+                        (KeyCode::Char('a'), KeyModifiers::NONE) => {
+                            app.start_file_operation(FileOperation::Create);
+                        }
+                        (KeyCode::Char('m'), KeyModifiers::NONE) => {
+                            app.start_file_operation(FileOperation::Rename);
+                        }
+                        (KeyCode::Char('c'), KeyModifiers::NONE) => {
+                            app.start_file_operation(FileOperation::Duplicate);
+                        }
+                        (KeyCode::Char('d'), KeyModifiers::NONE) | (KeyCode::Delete, _) => {
+                            app.start_file_operation(FileOperation::Delete);
+                        }
                         (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                            if !app.has_unsaved_changes() {
+                                return Ok(());
+                            }
                             app.current_screen = CurrentScreen::Exiting;
                         }
                         _ => {}
                     }
                 }
+                // confirming a delete from the file tree
+                CurrentScreen::ManagingFile
+                    if key.kind == KeyEventKind::Press
+                        && matches!(app.file_operation, Some(FileOperation::Delete)) =>
+                {
+                    match key.code {
+                        KeyCode::Enter | KeyCode::Char('y') => {
+                            app.finish_file_operation();
+                        }
+                        KeyCode::Esc | KeyCode::Char('n') => {
+                            app.cancel_file_operation();
+                        }
+                        _ => {}
+                    }
+                }
+                // typing the target path of a file operation
+                CurrentScreen::ManagingFile if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter => {
+                        app.finish_file_operation();
+                    }
+                    KeyCode::Backspace => {
+                        app.file_operation_input.pop();
+                    }
+                    KeyCode::Esc => {
+                        app.cancel_file_operation();
+                    }
+                    KeyCode::Char(value) => {
+                        app.file_operation_input.push(value);
+                    }
+                    _ => {}
+                },
+                // typing where to go
+                CurrentScreen::GoingTo if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter if app.go_to() => {
+                        app.current_screen = CurrentScreen::Main;
+                    }
+                    KeyCode::Backspace => {
+                        app.goto_input.pop();
+                    }
+                    KeyCode::Esc => {
+                        app.goto_input = String::new();
+                        app.current_screen = CurrentScreen::Main;
+                    }
+                    KeyCode::Char(value) => {
+                        app.goto_input.push(value);
+                    }
+                    _ => {}
+                },
+                // the list of every key of the main screen
+                CurrentScreen::Help => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        app.help_scroll = app.help_scroll.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        app.help_scroll = app.help_scroll.saturating_add(1);
+                    }
+                    KeyCode::PageUp => {
+                        app.help_scroll = app.help_scroll.saturating_sub(10);
+                    }
+                    KeyCode::PageDown => {
+                        app.help_scroll = app.help_scroll.saturating_add(10);
+                    }
+                    KeyCode::Esc | KeyCode::Char('?') | KeyCode::Char('q') => {
+                        app.current_screen = CurrentScreen::Main;
+                    }
+                    _ => {}
+                },
+                // typing an incremental search
+                CurrentScreen::Searching if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter => {
+                        app.current_screen = CurrentScreen::Main;
+                    }
+                    KeyCode::Esc => {
+                        app.cancel_search();
+                    }
+                    KeyCode::Tab => {
+                        app.search.next_mode();
+                        app.update_search();
+                    }
+                    KeyCode::BackTab => {
+                        app.search.next_scope();
+                        app.update_search();
+                    }
+                    KeyCode::Backspace => {
+                        app.search.query.pop();
+                        app.update_search();
+                    }
+                    KeyCode::Char(value) => {
+                        app.search.query.push(value);
+                        app.update_search();
+                    }
+                    _ => {}
+                },
+                // typing a query, its result shown live beside the document
+                CurrentScreen::Querying if key.kind == KeyEventKind::Press => {
+                    match (key.code, key.modifiers) {
+                        (KeyCode::Esc, _) => {
+                            app.current_screen = CurrentScreen::Main;
+                        }
+                        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                            app.replace_with_query(false);
+                        }
+                        (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                            app.replace_with_query(true);
+                        }
+                        (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                            app.start_saving_query();
+                        }
+                        (KeyCode::Up, _) => {
+                            app.select_previous(1);
+                        }
+                        (KeyCode::Down, _) => {
+                            app.select_next(1);
+                        }
+                        (KeyCode::PageUp, _) => {
+                            app.query_scroll = app.query_scroll.saturating_sub(10);
+                        }
+                        (KeyCode::PageDown, _) => {
+                            app.query_scroll = app.query_scroll.saturating_add(10);
+                        }
+                        (KeyCode::Backspace, _) => {
+                            app.query_input.pop();
+                            app.update_query();
+                        }
+                        (KeyCode::Char(value), _) => {
+                            app.query_input.push(value);
+                            app.update_query();
+                        }
+                        _ => {}
+                    }
+                }
+                // typing where to save the query result
+                CurrentScreen::SavingQuery if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter => {
+                        app.save_query_result();
+                    }
+                    KeyCode::Backspace => {
+                        app.query_output_input.pop();
+                    }
+                    KeyCode::Esc => {
+                        app.current_screen = CurrentScreen::Querying;
+                    }
+                    KeyCode::Char(value) => {
+                        app.query_output_input.push(value);
+                    }
+                    _ => {}
+                },
+                // typing what to find and what to replace it with
+                CurrentScreen::Replacing if key.kind == KeyEventKind::Press => {
+                    match (key.code, key.modifiers) {
+                        (KeyCode::Enter, _) => {
+                            app.preview_replacements();
+                        }
+                        (KeyCode::Esc, _) => {
+                            app.cancel_replace();
+                        }
+                        (KeyCode::Tab, _) => {
+                            app.typing_replacement = !app.typing_replacement;
+                        }
+                        (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
+                            app.search.next_mode();
+                        }
+                        (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                            app.search.next_scope();
+                        }
+                        (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
+                            app.replace_in_selection = !app.replace_in_selection;
+                        }
+                        (KeyCode::Backspace, _) if app.typing_replacement => {
+                            app.replace_input.pop();
+                        }
+                        (KeyCode::Backspace, _) => {
+                            app.search.query.pop();
+                        }
+                        (KeyCode::Char(value), _) if app.typing_replacement => {
+                            app.replace_input.push(value);
+                        }
+                        (KeyCode::Char(value), _) => {
+                            app.search.query.push(value);
+                        }
+                        _ => {}
+                    }
+                }
+                // going through the changes a replace would make
+                CurrentScreen::ConfirmingReplace if key.kind == KeyEventKind::Press => {
+                    let count = app.replacements.len();
+                    let selected = app.replacements_state.selected().unwrap_or_default();
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            app.replacements_state
+                                .select(Some(selected.saturating_sub(1)));
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            app.replacements_state
+                                .select(Some((selected + 1).min(count.saturating_sub(1))));
+                        }
+                        KeyCode::Char('y') => {
+                            app.decide_replacement(true);
+                        }
+                        KeyCode::Char('n') => {
+                            app.decide_replacement(false);
+                        }
+                        KeyCode::Char(' ') => {
+                            app.toggle_replacement();
+                        }
+                        KeyCode::Char('a') => {
+                            app.apply_replacements(true);
+                        }
+                        KeyCode::Enter => {
+                            app.apply_replacements(false);
+                        }
+                        KeyCode::Esc => {
+                            app.cancel_replace();
+                        }
+                        _ => {}
+                    }
+                }
+                // typing the path of a patch file
+                CurrentScreen::Patching if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter => {
+                        app.finish_patch_action();
+                    }
+                    KeyCode::Backspace => {
+                        app.patch_input.pop();
+                    }
+                    KeyCode::Esc => {
+                        app.cancel_patch_action();
+                    }
+                    KeyCode::Char(value) => {
+                        app.patch_input.push(value);
+                    }
+                    _ => {}
+                },
+                // previewing a patch before it is applied
+                CurrentScreen::ConfirmingPatch if key.kind == KeyEventKind::Press => match key.code
+                {
+                    KeyCode::Enter | KeyCode::Char('y') => {
+                        app.apply_pending_patch();
+                    }
+                    KeyCode::Esc | KeyCode::Char('n') => {
+                        app.cancel_patch_action();
+                    }
+                    _ => {}
+                },
+                // typing a name filter for the file tree
+                CurrentScreen::FilteringTree if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Enter => {
+                        app.current_screen = CurrentScreen::FileTree;
+                    }
+                    KeyCode::Esc => {
+                        app.file_tree.filter.name.clear();
+                        app.file_tree.filter_changed();
+                        app.current_screen = CurrentScreen::FileTree;
+                    }
+                    KeyCode::Backspace => {
+                        app.file_tree.filter.name.pop();
+                        app.file_tree.filter_changed();
+                    }
+                    KeyCode::Char(value) => {
+                        app.file_tree.filter.name.push(value);
+                        app.file_tree.filter_changed();
+                    }
+                    _ => {}
+                },
                 _ => {}
             }
         }
     }
 }
+
+/// Saves from the save as popup. Returns true when that was the last unnamed
+/// tab of a save and quit, so the editor should exit.
+fn finish_save_as(app: &mut App) -> bool {
+    if !app.save_as() {
+        return false;
+    }
+    if app.quit_after_save && app.save_all() {
+        return true;
+    }
+    // when quitting, the next tab without a name is asked for
+    if !app.quit_after_save || app.file_path.is_some() {
+        app.quit_after_save = false;
+        app.current_screen = CurrentScreen::Main;
+    }
+    false
+}
@@ -0,0 +1,425 @@
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+
+use crate::diff::{diff, Change};
+use crate::document::{get, get_mut, parse_pointer, pointer, JsonPath, PathSegment};
+
+/// Why a JSON Patch could not be applied.
+#[derive(Debug)]
+pub enum PatchError {
+    NotAPatch,                    // not an array of operation objects
+    Invalid(usize, &'static str), // operation number and what is wrong with it
+    NoTarget(usize, String),      // operation number and the pointer that does not exist
+    TestFailed(usize, String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::NotAPatch => write!(f, "a json patch is an array of operations"),
+            PatchError::Invalid(index, reason) => write!(f, "operation {index}: {reason}"),
+            PatchError::NoTarget(index, path) => write!(f, "operation {index}: nothing at {path}"),
+            PatchError::TestFailed(index, path) => {
+                write!(f, "operation {index}: test of {path} failed")
+            }
+        }
+    }
+}
+
+pub fn add_operation(path: &[PathSegment], value: Value) -> Value {
+    json!({ "op": "add", "path": pointer(path), "value": value })
+}
+
+pub fn replace_operation(path: &[PathSegment], value: Value) -> Value {
+    json!({ "op": "replace", "path": pointer(path), "value": value })
+}
+
+pub fn remove_operation(path: &[PathSegment]) -> Value {
+    json!({ "op": "remove", "path": pointer(path) })
+}
+
+pub fn move_operation(from: &[PathSegment], path: &[PathSegment]) -> Value {
+    json!({ "op": "move", "from": pointer(from), "path": pointer(path) })
+}
+
+/// Operations turning `before` into `after`, worked out from their differences.
+/// Removals come first and from the back, so array indices stay valid.
+pub fn operations_between(before: &Value, after: &Value) -> Vec<Value> {
+    let differences = diff(before, after);
+    let value_at = |path: &[PathSegment]| get(after, path).cloned().unwrap_or(Value::Null);
+    let removals = differences
+        .iter()
+        .rev()
+        .filter(|difference| difference.change == Change::Removed)
+        .map(|difference| remove_operation(&difference.path));
+    let others = differences
+        .iter()
+        .filter_map(|difference| match difference.change {
+            Change::Added => Some(add_operation(&difference.path, value_at(&difference.path))),
+            Change::Changed => Some(replace_operation(
+                &difference.path,
+                value_at(&difference.path),
+            )),
+            Change::Removed => None,
+        });
+    removals.chain(others.collect::<Vec<_>>()).collect()
+}
+
+/// Applies an RFC 6902 JSON Patch. All operations are applied or, on the first
+/// failing one, none: `document` is only replaced once every operation worked.
+pub fn apply(document: &mut Value, patch: &Value) -> Result<(), PatchError> {
+    let Value::Array(operations) = patch else {
+        return Err(PatchError::NotAPatch);
+    };
+    let mut result = document.clone();
+    for (index, operation) in operations.iter().enumerate() {
+        apply_operation(&mut result, operation, index + 1)?;
+    }
+    *document = result;
+    Ok(())
+}
+
+fn apply_operation(
+    document: &mut Value,
+    operation: &Value,
+    index: usize,
+) -> Result<(), PatchError> {
+    let member = |name: &'static str| operation.get(name).ok_or(PatchError::Invalid(index, name));
+    let pointer_member = |name: &'static str| {
+        member(name)?
+            .as_str()
+            .ok_or(PatchError::Invalid(index, "pointers must be strings"))
+    };
+    let op = member("op")?
+        .as_str()
+        .ok_or(PatchError::Invalid(index, "op must be a string"))?;
+    let path = pointer_member("path")?;
+    match op {
+        "add" => add(document, path, member("value")?.clone(), index),
+        "remove" => remove(document, path, index).map(|_| ()),
+        // in place, so a replaced member keeps its position
+        "replace" => {
+            let value = member("value")?.clone();
+            let target = resolve(document, path, index)?;
+            *get_mut(document, &target).ok_or_else(|| no_target(index, path))? = value;
+            Ok(())
+        }
+        "move" => {
+            let from = pointer_member("from")?;
+            if path.starts_with(from) && path[from.len()..].starts_with('/') {
+                return Err(PatchError::Invalid(
+                    index,
+                    "cannot move a value into itself",
+                ));
+            }
+            let value = remove(document, from, index)?;
+            add(document, path, value, index)
+        }
+        "copy" => {
+            let from = pointer_member("from")?;
+            let source = resolve(document, from, index)?;
+            let value = get(document, &source)
+                .cloned()
+                .ok_or_else(|| no_target(index, from))?;
+            add(document, path, value, index)
+        }
+        "test" => {
+            let target = resolve(document, path, index)?;
+            match get(document, &target) {
+                Some(value) if value == member("value")? => Ok(()),
+                _ => Err(PatchError::TestFailed(index, path.to_string())),
+            }
+        }
+        _ => Err(PatchError::Invalid(index, "unknown op")),
+    }
+}
+
+fn no_target(index: usize, pointer: &str) -> PatchError {
+    PatchError::NoTarget(index, pointer.to_string())
+}
+
+/// The path a pointer of operation `index` stands for in `document` as it is now.
+fn resolve(document: &Value, pointer: &str, index: usize) -> Result<JsonPath, PatchError> {
+    parse_pointer(document, pointer).map_err(|_| PatchError::Invalid(index, "not a json pointer"))
+}
+
+/// Adds `value` at `path`: a member is inserted or replaced, an array item is
+/// inserted before the one at its index, or appended at the end.
+fn add(document: &mut Value, path: &str, value: Value, index: usize) -> Result<(), PatchError> {
+    let target = resolve(document, path, index)?;
+    let Some((last, parent)) = target.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    match (get_mut(document, parent), last) {
+        (Some(Value::Object(map)), PathSegment::Key(key)) => {
+            map.insert(key.clone(), value);
+            Ok(())
+        }
+        (Some(Value::Array(items)), PathSegment::Index(position)) if *position <= items.len() => {
+            items.insert(*position, value);
+            Ok(())
+        }
+        _ => Err(no_target(index, path)),
+    }
+}
+
+fn remove(document: &mut Value, path: &str, index: usize) -> Result<Value, PatchError> {
+    let target = resolve(document, path, index)?;
+    let Some((last, parent)) = target.split_last() else {
+        return Ok(std::mem::take(document));
+    };
+    match (get_mut(document, parent), last) {
+        (Some(Value::Object(map)), PathSegment::Key(key)) => {
+            map.shift_remove(key).ok_or_else(|| no_target(index, path))
+        }
+        (Some(Value::Array(items)), PathSegment::Index(position)) if *position < items.len() => {
+            Ok(items.remove(*position))
+        }
+        _ => Err(no_target(index, path)),
+    }
+}
+
+/// Applies an RFC 7396 JSON Merge Patch: objects are merged key by key, `null`
+/// removes a key and anything else replaces the target.
+pub fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.shift_remove(key);
+            } else {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// The merge patch that turns `from` into `to`. Merge patches can not set a
+/// value to `null` inside an object, so such members come out as removals.
+pub fn merge_patch_between(from: &Value, to: &Value) -> Value {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut patch = Map::new();
+            for key in from.keys().filter(|key| !to.contains_key(*key)) {
+                patch.insert(key.clone(), Value::Null);
+            }
+            for (key, value) in to {
+                match from.get(key) {
+                    Some(old) if old == value => {}
+                    Some(old) => {
+                        patch.insert(key.clone(), merge_patch_between(old, value));
+                    }
+                    None => {
+                        patch.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Value::Object(patch)
+        }
+        (_, to) => to.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn applied(document: Value, patch: Value) -> Result<Value, PatchError> {
+        let mut document = document;
+        apply(&mut document, &patch)?;
+        Ok(document)
+    }
+
+    #[test]
+    fn operations_between_round_trip_through_apply() {
+        let before = json!({
+            "name": "api",
+            "ports": [80, 443, 8080],
+            "tags": ["a", "b", "c"],
+            "env": {"debug": true, "level": 1},
+            "gone": null
+        });
+        let after = json!({
+            "name": "web",
+            "ports": [443],
+            "tags": ["a", "b", "c", "d", "e"],
+            "env": {"level": 2, "mode": {"fast": true}},
+            "new": [1, {"x": 2}]
+        });
+        let patch = Value::Array(operations_between(&before, &after));
+        assert_eq!(applied(before, patch).unwrap(), after);
+    }
+
+    #[test]
+    fn operations_between_equal_values_is_empty() {
+        let value = json!({"a": [1, 2], "b": {"c": null}});
+        assert!(operations_between(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn rfc_6902_operations() {
+        let document = json!({"foo": ["bar", "baz"], "x": {"y": 1}});
+        let patch = json!([
+            {"op": "add", "path": "/foo/1", "value": "qux"},
+            {"op": "add", "path": "/foo/-", "value": "end"},
+            {"op": "replace", "path": "/x/y", "value": 2},
+            {"op": "copy", "from": "/x", "path": "/z"},
+            {"op": "move", "from": "/foo/0", "path": "/first"},
+            {"op": "remove", "path": "/x"},
+            {"op": "test", "path": "/z/y", "value": 2}
+        ]);
+        assert_eq!(
+            applied(document, patch).unwrap(),
+            json!({"foo": ["qux", "baz", "end"], "z": {"y": 2}, "first": "bar"})
+        );
+    }
+
+    #[test]
+    fn replace_keeps_the_position_of_a_member() {
+        let document = applied(
+            json!({"a": 1, "b": 2, "c": 3}),
+            json!([{"op": "replace", "path": "/b", "value": 20}]),
+        )
+        .unwrap();
+        let keys: Vec<&String> = document.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn a_failing_test_leaves_the_document_untouched() {
+        let mut document = json!({"a": 1});
+        let patch = json!([
+            {"op": "add", "path": "/b", "value": 2},
+            {"op": "test", "path": "/a", "value": "1"}
+        ]);
+        assert!(matches!(
+            apply(&mut document, &patch),
+            Err(PatchError::TestFailed(2, path)) if path == "/a"
+        ));
+        assert_eq!(document, json!({"a": 1}));
+    }
+
+    #[test]
+    fn testing_a_missing_value_fails() {
+        assert!(matches!(
+            applied(
+                json!({"a": [1]}),
+                json!([{"op": "test", "path": "/a/1", "value": null}])
+            ),
+            Err(PatchError::TestFailed(1, _))
+        ));
+    }
+
+    #[test]
+    fn invalid_operations() {
+        let document = json!({"a": [1]});
+        assert!(matches!(
+            applied(document.clone(), json!({"op": "add"})),
+            Err(PatchError::NotAPatch)
+        ));
+        assert!(matches!(
+            applied(
+                document.clone(),
+                json!([{"op": "add", "path": "a", "value": 1}])
+            ),
+            Err(PatchError::Invalid(1, _))
+        ));
+        assert!(matches!(
+            applied(document.clone(), json!([{"op": "remove", "path": "/a/-"}])),
+            Err(PatchError::NoTarget(1, _))
+        ));
+        assert!(matches!(
+            applied(
+                document.clone(),
+                json!([{"op": "add", "path": "/a/5", "value": 1}])
+            ),
+            Err(PatchError::NoTarget(1, _))
+        ));
+        assert!(matches!(
+            applied(
+                document,
+                json!([{"op": "move", "from": "/a", "path": "/a/0"}])
+            ),
+            Err(PatchError::Invalid(1, _))
+        ));
+    }
+
+    /// The examples of RFC 7396, appendix A.
+    #[test]
+    fn rfc_7396_merge_patches() {
+        let cases = [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (
+                json!({"a": "b", "b": "c"}),
+                json!({"a": null}),
+                json!({"b": "c"}),
+            ),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (
+                json!({"a": {"b": "c"}}),
+                json!({"a": {"b": "d", "c": null}}),
+                json!({"a": {"b": "d"}}),
+            ),
+            (
+                json!({"a": [{"b": "c"}]}),
+                json!({"a": [1]}),
+                json!({"a": [1]}),
+            ),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ];
+        for (target, patch, expected) in cases {
+            let mut merged = target.clone();
+            merge(&mut merged, &patch);
+            assert_eq!(merged, expected, "merging {patch} into {target}");
+        }
+    }
+
+    #[test]
+    fn merge_patch_between_round_trips() {
+        let from = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1]});
+        let to = json!({"b": {"c": 2, "d": 4, "f": true}, "e": [1, 2], "g": "new"});
+        let patch = merge_patch_between(&from, &to);
+        assert_eq!(
+            patch,
+            json!({"a": null, "b": {"d": 4, "f": true}, "e": [1, 2], "g": "new"})
+        );
+        let mut merged = from;
+        merge(&mut merged, &patch);
+        assert_eq!(merged, to);
+    }
+}
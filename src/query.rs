@@ -0,0 +1,778 @@
+use std::cmp::Ordering;
+
+use serde_json::{Map, Value};
+
+/// A parsed jq-like filter. Like in jq every filter takes one input value and
+/// produces any number of outputs.
+enum Filter {
+    Identity,
+    Literal(Value),
+    Field(Box<Filter>, String),      // `.name`
+    Index(Box<Filter>, Box<Filter>), // `.[0]`, `.["name"]`
+    Slice(Box<Filter>, Option<Box<Filter>>, Option<Box<Filter>>), // `.[1:3]`
+    Iterate(Box<Filter>),            // `.[]`
+    Pipe(Box<Filter>, Box<Filter>),
+    Comma(Box<Filter>, Box<Filter>),
+    Compare(Box<Filter>, &'static str, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Array(Option<Box<Filter>>),
+    Object(Vec<(Filter, Filter)>),
+    Call(String, Vec<Filter>),
+}
+
+/// Runs a jq-like `expression` such as `.servers[] | select(.port > 80) | .host`
+/// on `input`. Supports paths (`.a.b`, `.[0]`, `.[1:3]`, `.[]`), `|`, `,`,
+/// literals, `[...]` and `{...}`, comparisons, `and`, `or` and the functions
+/// `select`, `map`, `keys`, `length`, `not`, `has` and `type`.
+pub fn run(input: &Value, expression: &str) -> Result<Vec<Value>, String> {
+    let tokens = tokenize(expression)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+    };
+    let filter = parser.pipe()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected `{}`", token.text()));
+    }
+    eval(&filter, input)
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Dot,
+    FieldName(String), // `.name`
+    Name(String),
+    Literal(Value),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Dot => String::from("."),
+            Token::FieldName(name) => format!(".{name}"),
+            Token::Name(name) => name.clone(),
+            Token::Literal(value) => value.to_string(),
+            Token::Symbol(symbol) => symbol.to_string(),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 15] = [
+    "==", "!=", "<=", ">=", "<", ">", "|", ",", ":", "[", "]", "(", ")", "{", "}",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let is_name = |character: char| character.is_alphanumeric() || character == '_';
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while let Some(character) = rest.chars().next() {
+        if character == '.' {
+            let name_length = rest[1..].find(|c| !is_name(c)).unwrap_or(rest.len() - 1);
+            if name_length > 0 && !rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::FieldName(rest[1..=name_length].to_string()));
+                rest = &rest[1 + name_length..];
+            } else if let Some(quoted) = rest[1..].strip_prefix('"') {
+                // `."name with spaces"` is `.["name with spaces"]`
+                let (name, after) = string_literal(quoted)?;
+                tokens.push(Token::FieldName(name));
+                rest = after;
+            } else {
+                tokens.push(Token::Dot);
+                rest = &rest[1..];
+            }
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let (text, after) = string_literal(quoted)?;
+            tokens.push(Token::Literal(Value::String(text)));
+            rest = after;
+        } else if character.is_ascii_digit()
+            || (character == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            // a sign only belongs to the number right after the e of an exponent
+            let mut previous = character;
+            let length = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| {
+                    let exponent_sign = matches!(c, '+' | '-') && matches!(previous, 'e' | 'E');
+                    previous = c;
+                    !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E') || exponent_sign)
+                })
+                .map_or(rest.len(), |(length, _)| length);
+            let number: Value = serde_json::from_str(&rest[..length])
+                .map_err(|_| format!("`{}` is not a number", &rest[..length]))?;
+            tokens.push(Token::Literal(number));
+            rest = &rest[length..];
+        } else if is_name(character) {
+            let length = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+            let token = match &rest[..length] {
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                "null" => Token::Literal(Value::Null),
+                name => Token::Name(name.to_string()),
+            };
+            tokens.push(token);
+            rest = &rest[length..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            return Err(format!("unexpected `{character}`"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// A string literal whose opening quote has been read, and what follows it.
+fn string_literal(quoted: &str) -> Result<(String, &str), String> {
+    let mut escaped = false;
+    for (index, character) in quoted.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => {
+                let text = serde_json::from_str(&format!("\"{}\"", &quoted[..index]))
+                    .map_err(|err| format!("invalid string: {err}"))?;
+                return Ok((text, &quoted[index + 1..]));
+            }
+            _ => {}
+        }
+    }
+    Err(String::from("missing closing \""))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), String> {
+        if self.eat(&Token::Symbol(symbol)) {
+            Ok(())
+        } else {
+            match self.peek() {
+                Some(token) => Err(format!("expected `{symbol}` but found `{}`", token.text())),
+                None => Err(format!("expected `{symbol}`")),
+            }
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Filter, String> {
+        let mut filter = self.comma()?;
+        while self.eat(&Token::Symbol("|")) {
+            filter = Filter::Pipe(Box::new(filter), Box::new(self.comma()?));
+        }
+        Ok(filter)
+    }
+
+    fn comma(&mut self) -> Result<Filter, String> {
+        let mut filter = self.or()?;
+        while self.eat(&Token::Symbol(",")) {
+            filter = Filter::Comma(Box::new(filter), Box::new(self.or()?));
+        }
+        Ok(filter)
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.eat(&Token::Name(String::from("or"))) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.comparison()?;
+        while self.eat(&Token::Name(String::from("and"))) {
+            filter = Filter::And(Box::new(filter), Box::new(self.comparison()?));
+        }
+        Ok(filter)
+    }
+
+    fn comparison(&mut self) -> Result<Filter, String> {
+        let left = self.postfix()?;
+        let operator = match self.peek() {
+            Some(Token::Symbol(symbol @ ("==" | "!=" | "<" | "<=" | ">" | ">="))) => *symbol,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.postfix()?;
+        Ok(Filter::Compare(Box::new(left), operator, Box::new(right)))
+    }
+
+    /// A term followed by any number of `.name`, `[...]` and `.[...]`.
+    fn postfix(&mut self) -> Result<Filter, String> {
+        let mut filter = self.term()?;
+        loop {
+            filter = match self.peek() {
+                Some(Token::FieldName(name)) => {
+                    let name = name.clone();
+                    self.position += 1;
+                    Filter::Field(Box::new(filter), name)
+                }
+                Some(Token::Dot)
+                    if self.tokens.get(self.position + 1) == Some(&Token::Symbol("[")) =>
+                {
+                    self.position += 1;
+                    continue;
+                }
+                Some(Token::Symbol("[")) => {
+                    self.position += 1;
+                    self.brackets(filter)?
+                }
+                _ => return Ok(filter),
+            };
+        }
+    }
+
+    /// What follows the `[` of `.[]`, `.[index]` or `.[start:end]`.
+    fn brackets(&mut self, filter: Filter) -> Result<Filter, String> {
+        let filter = Box::new(filter);
+        if self.eat(&Token::Symbol("]")) {
+            return Ok(Filter::Iterate(filter));
+        }
+        let start = if self.peek() == Some(&Token::Symbol(":")) {
+            None
+        } else {
+            Some(Box::new(self.pipe()?))
+        };
+        if self.eat(&Token::Symbol(":")) {
+            let end = if self.peek() == Some(&Token::Symbol("]")) {
+                None
+            } else {
+                Some(Box::new(self.pipe()?))
+            };
+            self.expect("]")?;
+            return Ok(Filter::Slice(filter, start, end));
+        }
+        self.expect("]")?;
+        match start {
+            Some(index) => Ok(Filter::Index(filter, index)),
+            None => Err(String::from("empty []")),
+        }
+    }
+
+    fn term(&mut self) -> Result<Filter, String> {
+        match self.next() {
+            Some(Token::Dot) => Ok(Filter::Identity),
+            // `.name` on its own is a field of the input
+            Some(Token::FieldName(name)) => Ok(Filter::Field(Box::new(Filter::Identity), name)),
+            Some(Token::Literal(value)) => Ok(Filter::Literal(value)),
+            Some(Token::Symbol("(")) => {
+                let filter = self.pipe()?;
+                self.expect(")")?;
+                Ok(filter)
+            }
+            Some(Token::Symbol("[")) => {
+                if self.eat(&Token::Symbol("]")) {
+                    return Ok(Filter::Array(None));
+                }
+                let filter = self.pipe()?;
+                self.expect("]")?;
+                Ok(Filter::Array(Some(Box::new(filter))))
+            }
+            Some(Token::Symbol("{")) => self.object(),
+            Some(Token::Name(name)) => {
+                let mut arguments = Vec::new();
+                if self.eat(&Token::Symbol("(")) {
+                    arguments.push(self.pipe()?);
+                    self.expect(")")?;
+                }
+                Ok(Filter::Call(name, arguments))
+            }
+            Some(token) => Err(format!("unexpected `{}`", token.text())),
+            None => Err(String::from("unexpected end of the query")),
+        }
+    }
+
+    /// What follows the `{` of an object: `name: filter`, `"name": filter`,
+    /// `(filter): filter` or just `name` for `name: .name`.
+    fn object(&mut self) -> Result<Filter, String> {
+        let mut members = Vec::new();
+        if self.eat(&Token::Symbol("}")) {
+            return Ok(Filter::Object(members));
+        }
+        loop {
+            let key = match self.next() {
+                Some(Token::Name(name)) => name,
+                Some(Token::Literal(Value::String(name))) => name,
+                Some(Token::Symbol("(")) => {
+                    let key = self.pipe()?;
+                    self.expect(")")?;
+                    self.expect(":")?;
+                    members.push((key, self.or()?));
+                    if self.eat(&Token::Symbol(",")) {
+                        continue;
+                    }
+                    self.expect("}")?;
+                    return Ok(Filter::Object(members));
+                }
+                _ => return Err(String::from("expected an object key")),
+            };
+            let value = if self.eat(&Token::Symbol(":")) {
+                self.or()?
+            } else {
+                Filter::Field(Box::new(Filter::Identity), key.clone())
+            };
+            members.push((Filter::Literal(Value::String(key)), value));
+            if !self.eat(&Token::Symbol(",")) {
+                self.expect("}")?;
+                return Ok(Filter::Object(members));
+            }
+        }
+    }
+}
+
+fn eval(filter: &Filter, input: &Value) -> Result<Vec<Value>, String> {
+    match filter {
+        Filter::Identity => Ok(vec![input.clone()]),
+        Filter::Literal(value) => Ok(vec![value.clone()]),
+        Filter::Field(base, name) => eval(base, input)?
+            .iter()
+            .map(|value| field(value, name))
+            .collect(),
+        Filter::Index(base, index) => {
+            let mut outputs = Vec::new();
+            for value in eval(base, input)? {
+                for index in eval(index, input)? {
+                    outputs.push(index_value(&value, &index)?);
+                }
+            }
+            Ok(outputs)
+        }
+        Filter::Slice(base, start, end) => {
+            let bound = |filter: &Option<Box<Filter>>| -> Result<Option<i64>, String> {
+                let Some(filter) = filter else {
+                    return Ok(None);
+                };
+                match eval(filter, input)?.first() {
+                    Some(Value::Number(number)) => Ok(number.as_f64().map(|number| number as i64)),
+                    Some(Value::Null) | None => Ok(None),
+                    Some(other) => Err(format!("cannot slice with {}", type_name(other))),
+                }
+            };
+            let (start, end) = (bound(start)?, bound(end)?);
+            eval(base, input)?
+                .iter()
+                .map(|value| slice(value, start, end))
+                .collect()
+        }
+        Filter::Iterate(base) => {
+            let mut outputs = Vec::new();
+            for value in eval(base, input)? {
+                outputs.extend(iterate(&value)?);
+            }
+            Ok(outputs)
+        }
+        Filter::Pipe(left, right) => {
+            let mut outputs = Vec::new();
+            for value in eval(left, input)? {
+                outputs.extend(eval(right, &value)?);
+            }
+            Ok(outputs)
+        }
+        Filter::Comma(left, right) => {
+            let mut outputs = eval(left, input)?;
+            outputs.extend(eval(right, input)?);
+            Ok(outputs)
+        }
+        Filter::Compare(left, operator, right) => {
+            let mut outputs = Vec::new();
+            for right in eval(right, input)? {
+                for left in eval(left, input)? {
+                    let ordering = compare(&left, &right);
+                    let result = match *operator {
+                        "==" => ordering == Ordering::Equal,
+                        "!=" => ordering != Ordering::Equal,
+                        "<" => ordering == Ordering::Less,
+                        "<=" => ordering != Ordering::Greater,
+                        ">" => ordering == Ordering::Greater,
+                        _ => ordering != Ordering::Less,
+                    };
+                    outputs.push(Value::Bool(result));
+                }
+            }
+            Ok(outputs)
+        }
+        Filter::And(left, right) | Filter::Or(left, right) => {
+            let or = matches!(filter, Filter::Or(..));
+            let mut outputs = Vec::new();
+            for left in eval(left, input)? {
+                // the right side is only looked at when it decides the result
+                if truthy(&left) == or {
+                    outputs.push(Value::Bool(or));
+                    continue;
+                }
+                for right in eval(right, input)? {
+                    outputs.push(Value::Bool(truthy(&right)));
+                }
+            }
+            Ok(outputs)
+        }
+        Filter::Array(None) => Ok(vec![Value::Array(Vec::new())]),
+        Filter::Array(Some(items)) => Ok(vec![Value::Array(eval(items, input)?)]),
+        Filter::Object(members) => {
+            // every combination of the members' outputs, as in jq
+            let mut objects = vec![Map::new()];
+            for (key, value) in members {
+                let mut next = Vec::new();
+                for key in eval(key, input)? {
+                    let Value::String(key) = key else {
+                        return Err(format!(
+                            "object keys must be strings, not {}",
+                            type_name(&key)
+                        ));
+                    };
+                    for value in eval(value, input)? {
+                        for object in &objects {
+                            let mut object = object.clone();
+                            object.insert(key.clone(), value.clone());
+                            next.push(object);
+                        }
+                    }
+                }
+                objects = next;
+            }
+            Ok(objects.into_iter().map(Value::Object).collect())
+        }
+        Filter::Call(name, arguments) => call(name, arguments, input),
+    }
+}
+
+fn call(name: &str, arguments: &[Filter], input: &Value) -> Result<Vec<Value>, String> {
+    match (name, arguments) {
+        ("select", [condition]) => Ok(eval(condition, input)?
+            .iter()
+            .filter(|value| truthy(value))
+            .map(|_| input.clone())
+            .collect()),
+        ("map", [function]) => {
+            let mut items = Vec::new();
+            for item in iterate(input)? {
+                items.extend(eval(function, &item)?);
+            }
+            Ok(vec![Value::Array(items)])
+        }
+        ("has", [key]) => eval(key, input)?
+            .iter()
+            .map(|key| match (input, key) {
+                (Value::Object(map), Value::String(key)) => Ok(Value::Bool(map.contains_key(key))),
+                (Value::Array(items), Value::Number(index)) => {
+                    Ok(Value::Bool(index.as_f64().is_some_and(|index| {
+                        index >= 0.0 && (index as usize) < items.len()
+                    })))
+                }
+                _ => Err(format!(
+                    "cannot check whether {} has a {} key",
+                    type_name(input),
+                    type_name(key)
+                )),
+            })
+            .collect(),
+        ("keys", []) => match input {
+            Value::Object(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                Ok(vec![Value::Array(
+                    keys.into_iter()
+                        .map(|key| Value::String(key.clone()))
+                        .collect(),
+                )])
+            }
+            Value::Array(items) => Ok(vec![Value::Array(
+                (0..items.len()).map(Value::from).collect(),
+            )]),
+            other => Err(format!("{} has no keys", type_name(other))),
+        },
+        ("length", []) => match input {
+            Value::Null => Ok(vec![Value::from(0)]),
+            Value::Number(number) => Ok(vec![match number.as_i64() {
+                Some(number) => Value::from(number.unsigned_abs()),
+                None => Value::from(number.as_f64().unwrap_or_default().abs()),
+            }]),
+            Value::String(text) => Ok(vec![Value::from(text.chars().count())]),
+            Value::Array(items) => Ok(vec![Value::from(items.len())]),
+            Value::Object(map) => Ok(vec![Value::from(map.len())]),
+            Value::Bool(_) => Err(String::from("boolean has no length")),
+        },
+        ("not", []) => Ok(vec![Value::Bool(!truthy(input))]),
+        ("type", []) => Ok(vec![Value::from(type_name(input))]),
+        ("select" | "map" | "has", _) => Err(format!("{name} takes one argument")),
+        ("keys" | "length" | "not" | "type", _) => Err(format!("{name} takes no arguments")),
+        _ => Err(format!("unknown function {name}")),
+    }
+}
+
+fn field(value: &Value, name: &str) -> Result<Value, String> {
+    match value {
+        Value::Object(map) => Ok(map.get(name).cloned().unwrap_or(Value::Null)),
+        Value::Null => Ok(Value::Null),
+        other => Err(format!("cannot get .{name} of {}", type_name(other))),
+    }
+}
+
+fn index_value(value: &Value, index: &Value) -> Result<Value, String> {
+    match (value, index) {
+        (Value::Object(_), Value::String(name)) => field(value, name),
+        (Value::Array(items), Value::Number(number)) => {
+            let index = number.as_f64().unwrap_or_default() as i64;
+            let index = if index < 0 {
+                items.len() as i64 + index
+            } else {
+                index
+            };
+            Ok(usize::try_from(index)
+                .ok()
+                .and_then(|index| items.get(index))
+                .cloned()
+                .unwrap_or(Value::Null))
+        }
+        (Value::Null, _) => Ok(Value::Null),
+        (value, index) => Err(format!(
+            "cannot index {} with {}",
+            type_name(value),
+            type_name(index)
+        )),
+    }
+}
+
+fn slice(value: &Value, start: Option<i64>, end: Option<i64>) -> Result<Value, String> {
+    let range = |len: usize| {
+        let clamp = |bound: i64| {
+            let bound = if bound < 0 { len as i64 + bound } else { bound };
+            bound.clamp(0, len as i64) as usize
+        };
+        let start = start.map_or(0, clamp);
+        let end = end.map_or(len, clamp);
+        start..end.max(start)
+    };
+    match value {
+        Value::Array(items) => Ok(Value::Array(items[range(items.len())].to_vec())),
+        Value::String(text) => {
+            let characters: Vec<char> = text.chars().collect();
+            Ok(Value::String(
+                characters[range(characters.len())].iter().collect(),
+            ))
+        }
+        Value::Null => Ok(Value::Null),
+        other => Err(format!("cannot slice {}", type_name(other))),
+    }
+}
+
+fn iterate(value: &Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(items) => Ok(items.clone()),
+        Value::Object(map) => Ok(map.values().cloned().collect()),
+        other => Err(format!("cannot iterate over {}", type_name(other))),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// jq's ordering: null, false, true, numbers, strings, arrays, then objects,
+/// each compared by value within its kind.
+fn compare(left: &Value, right: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    };
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => {
+            let (left, right) = (
+                left.as_f64().unwrap_or_default(),
+                right.as_f64().unwrap_or_default(),
+            );
+            left.partial_cmp(&right).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(left), Value::String(right)) => left.cmp(right),
+        (Value::Array(left), Value::Array(right)) => left
+            .iter()
+            .zip(right)
+            .map(|(left, right)| compare(left, right))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(left.len().cmp(&right.len())),
+        (Value::Object(left), Value::Object(right)) => {
+            let mut left_keys: Vec<&String> = left.keys().collect();
+            let mut right_keys: Vec<&String> = right.keys().collect();
+            left_keys.sort();
+            right_keys.sort();
+            left_keys.cmp(&right_keys).then_with(|| {
+                left_keys
+                    .iter()
+                    .map(|key| compare(&left[*key], &right[*key]))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => rank(left).cmp(&rank(right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn servers() -> Value {
+        json!({
+            "servers": [
+                {"host": "a", "port": 80, "tags": ["web"]},
+                {"host": "b", "port": 443},
+                {"host": "c", "port": 8080, "tags": []}
+            ],
+            "name": "prod"
+        })
+    }
+
+    fn one(input: &Value, expression: &str) -> Value {
+        let outputs = run(input, expression).unwrap();
+        assert_eq!(outputs.len(), 1, "{expression} gave {outputs:?}");
+        outputs.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn paths_and_iteration() {
+        let input = servers();
+        assert_eq!(one(&input, "."), input);
+        assert_eq!(one(&input, ".name"), json!("prod"));
+        assert_eq!(one(&input, ".servers[1].host"), json!("b"));
+        assert_eq!(one(&input, ".[\"name\"]"), json!("prod"));
+        assert_eq!(one(&input, ".missing.deeper"), Value::Null);
+        assert_eq!(
+            run(&input, ".servers[].host").unwrap(),
+            [json!("a"), json!("b"), json!("c")]
+        );
+        assert_eq!(
+            run(&input, ".name, .servers[0].port").unwrap(),
+            [json!("prod"), json!(80)]
+        );
+    }
+
+    #[test]
+    fn negative_indices_and_slices() {
+        let input = json!([1, 2, 3, 4, 5]);
+        assert_eq!(one(&input, ".[-1]"), json!(5));
+        assert_eq!(one(&input, ".[-6]"), Value::Null);
+        assert_eq!(one(&input, ".[1:3]"), json!([2, 3]));
+        assert_eq!(one(&input, ".[-2:]"), json!([4, 5]));
+        assert_eq!(one(&input, ".[:-3]"), json!([1, 2]));
+        assert_eq!(one(&input, ".[3:1]"), json!([]));
+        assert_eq!(one(&json!("hello"), ".[1:3]"), json!("el"));
+    }
+
+    #[test]
+    fn select_and_comparisons() {
+        let input = servers();
+        assert_eq!(
+            one(&input, "[.servers[] | select(.port > 80) | .host]"),
+            json!(["b", "c"])
+        );
+        assert_eq!(
+            one(
+                &input,
+                "[.servers[] | select(.port >= 443 and .host != \"c\") | .host]"
+            ),
+            json!(["b"])
+        );
+        assert_eq!(
+            one(&input, "[.servers[] | select(has(\"tags\") | not) | .host]"),
+            json!(["b"])
+        );
+        assert_eq!(one(&input, ".servers[0].port == 80"), json!(true));
+        assert_eq!(one(&input, ".servers[0].port <= 79"), json!(false));
+        assert_eq!(one(&input, ".name < \"zzz\""), json!(true));
+        // jq's order across kinds: null < false < numbers < strings
+        assert_eq!(one(&input, "null < false"), json!(true));
+        assert_eq!(one(&input, "1 < \"1\""), json!(true));
+    }
+
+    #[test]
+    fn map_keys_and_length() {
+        let input = servers();
+        assert_eq!(one(&input, ".servers | map(.port)"), json!([80, 443, 8080]));
+        assert_eq!(one(&input, "keys"), json!(["name", "servers"]));
+        assert_eq!(one(&input, ".servers | keys"), json!([0, 1, 2]));
+        assert_eq!(one(&input, ".servers | length"), json!(3));
+        assert_eq!(one(&input, ".name | length"), json!(4));
+        assert_eq!(one(&input, ".servers[0] | length"), json!(3));
+        assert_eq!(one(&input, "null | length"), json!(0));
+        assert_eq!(one(&input, "-5 | length"), json!(5));
+        assert!(run(&input, "true | length").is_err());
+    }
+
+    #[test]
+    fn objects_and_arrays() {
+        let input = servers();
+        assert_eq!(
+            one(&input, "{name, first: .servers[0].host}"),
+            json!({"name": "prod", "first": "a"})
+        );
+        assert_eq!(
+            run(&input, "{host: .servers[].host}").unwrap().len(),
+            3,
+            "one object per output, as in jq"
+        );
+        assert_eq!(one(&input, "[]"), json!([]));
+    }
+
+    #[test]
+    fn numbers() {
+        let input = Value::Null;
+        assert_eq!(one(&input, "1e-5"), json!(0.00001));
+        assert_eq!(one(&input, "2E+3"), json!(2000.0));
+        assert_eq!(one(&input, "-1.5e2"), json!(-150.0));
+        assert_eq!(one(&input, "[1e-5 < 1]"), json!([true]));
+        assert!(run(&input, "1e").is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let input = servers();
+        assert!(run(&input, ".name.first").is_err());
+        assert!(run(&input, ".servers[").is_err());
+        assert!(run(&input, "nope").is_err());
+        assert!(run(&input, "select(.a; .b)").is_err());
+        assert!(run(&input, ".name | .[]").is_err());
+        assert!(run(&input, "\"unterminated").is_err());
+    }
+}
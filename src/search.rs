@@ -0,0 +1,283 @@
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde_json::Value;
+
+use crate::document::{descendants, get, JsonPath, PathSegment};
+
+/// How the search text is matched.
+#[derive(Clone, Copy)]
+pub enum SearchMode {
+    Plain,      // the text as typed
+    IgnoreCase, // the text in any case
+    Regex,      // the text is a regular expression
+}
+
+/// What the search looks at.
+#[derive(Clone, Copy)]
+pub enum SearchScope {
+    Both,
+    Keys,
+    Values,
+}
+
+/// One change a find and replace would make: the key or the value at `path`
+/// going from `before` to `after`.
+pub struct Replacement {
+    pub path: JsonPath,
+    pub key: bool, // the key is renamed rather than the value replaced
+    pub before: String,
+    pub after: String,
+    pub accepted: bool,
+    pub collides: bool, // the new key is already taken, renaming would drop a value
+}
+
+pub struct Search {
+    pub query: String,
+    pub mode: SearchMode,
+    pub scope: SearchScope,
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search {
+            query: String::new(),
+            mode: SearchMode::Plain,
+            scope: SearchScope::Both,
+        }
+    }
+
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            SearchMode::Plain => SearchMode::IgnoreCase,
+            SearchMode::IgnoreCase => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::Plain,
+        };
+    }
+
+    pub fn next_scope(&mut self) {
+        self.scope = match self.scope {
+            SearchScope::Both => SearchScope::Keys,
+            SearchScope::Keys => SearchScope::Values,
+            SearchScope::Values => SearchScope::Both,
+        };
+    }
+
+    /// A short description of the mode and scope, like "ignore case, keys".
+    pub fn describe(&self) -> String {
+        let mode = match self.mode {
+            SearchMode::Plain => "plain",
+            SearchMode::IgnoreCase => "ignore case",
+            SearchMode::Regex => "regex",
+        };
+        let scope = match self.scope {
+            SearchScope::Both => "keys and values",
+            SearchScope::Keys => "keys",
+            SearchScope::Values => "values",
+        };
+        format!("{mode}, {scope}")
+    }
+
+    /// The query as a regular expression, whatever the mode.
+    pub fn regex(&self) -> Result<Regex, regex::Error> {
+        let pattern = match self.mode {
+            SearchMode::Regex => self.query.clone(),
+            SearchMode::Plain | SearchMode::IgnoreCase => regex::escape(&self.query),
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(matches!(self.mode, SearchMode::IgnoreCase))
+            .build()
+    }
+
+    /// The paths of every key or value the query matches, in document order.
+    /// Strings are matched without their quotes, other scalars by their json
+    /// text; objects and arrays only by their key.
+    pub fn find(&self, root: &Value) -> Result<Vec<JsonPath>, regex::Error> {
+        let regex = self.regex()?;
+        if self.query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut found = Vec::new();
+        for (path, value) in descendants(root, &JsonPath::new()) {
+            let key_matches = self.key_text(&path).is_some_and(|key| regex.is_match(key));
+            let value_matches = self
+                .value_text(value)
+                .is_some_and(|text| regex.is_match(&text));
+            if key_matches || value_matches {
+                found.push(path);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Every change replacing the matches with `replacement` would make at
+    /// `under` and below it, in document order. In regex mode `$1` or `${name}`
+    /// in the replacement stand for capture groups.
+    pub fn replacements(
+        &self,
+        root: &Value,
+        under: &JsonPath,
+        replacement: &str,
+    ) -> Result<Vec<Replacement>, regex::Error> {
+        let regex = self.regex()?;
+        if self.query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(start) = get(root, under) else {
+            return Ok(Vec::new());
+        };
+        let replace = |text: &str| match self.mode {
+            SearchMode::Regex => regex.replace_all(text, replacement).into_owned(),
+            SearchMode::Plain | SearchMode::IgnoreCase => {
+                regex.replace_all(text, NoExpand(replacement)).into_owned()
+            }
+        };
+        let mut found = Vec::new();
+        let below = descendants(start, under);
+        for (path, value) in std::iter::once((under.clone(), start)).chain(below) {
+            let texts = [
+                (true, self.key_text(&path).map(String::from)),
+                (false, self.value_text(value)),
+            ];
+            for (key, text) in texts {
+                let Some(before) = text else {
+                    continue;
+                };
+                let after = replace(&before);
+                if after != before {
+                    found.push(Replacement {
+                        path: path.clone(),
+                        key,
+                        before,
+                        after,
+                        accepted: false,
+                        collides: false,
+                    });
+                }
+            }
+        }
+        mark_collisions(root, &mut found);
+        Ok(found)
+    }
+
+    /// The key the search looks at for `path`, if any: array items and the
+    /// root have none.
+    fn key_text<'a>(&self, path: &'a JsonPath) -> Option<&'a str> {
+        match (self.scope, path.last()) {
+            (SearchScope::Values, _) => None,
+            (_, Some(PathSegment::Key(key))) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn value_text(&self, value: &Value) -> Option<String> {
+        match self.scope {
+            SearchScope::Keys => None,
+            SearchScope::Both | SearchScope::Values => scalar_text(value),
+        }
+    }
+}
+
+/// Marks the renames whose new key is already in the object, or is also the new
+/// key of another rename there. Renames are made one after the other, so even
+/// two keys swapping names would lose one of the values.
+fn mark_collisions(root: &Value, replacements: &mut [Replacement]) {
+    let renames: Vec<(JsonPath, String)> = replacements
+        .iter()
+        .filter(|replacement| replacement.key)
+        .map(|replacement| (parent(&replacement.path), replacement.after.clone()))
+        .collect();
+    for replacement in replacements
+        .iter_mut()
+        .filter(|replacement| replacement.key)
+    {
+        let parent = parent(&replacement.path);
+        let taken = get(root, &parent)
+            .and_then(Value::as_object)
+            .is_some_and(|map| map.contains_key(&replacement.after));
+        let shared = renames
+            .iter()
+            .filter(|(other, after)| *other == parent && *after == replacement.after)
+            .count()
+            > 1;
+        replacement.collides = taken || shared;
+    }
+}
+
+fn parent(path: &[PathSegment]) -> JsonPath {
+    path.split_last()
+        .map(|(_, parent)| parent.to_vec())
+        .unwrap_or_default()
+}
+
+/// The value a replaced text stands for: strings stay strings, the text of
+/// other scalars is parsed again and kept as a string when it is no longer
+/// a json scalar.
+pub fn replaced_value(old: &Value, text: String) -> Value {
+    if old.is_string() {
+        return Value::String(text);
+    }
+    match serde_json::from_str::<Value>(&text) {
+        Ok(value) if !value.is_object() && !value.is_array() => value,
+        _ => Value::String(text),
+    }
+}
+
+/// The text a search sees for a value: strings without their quotes, other
+/// scalars as json and nothing for objects and arrays.
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Object(_) | Value::Array(_) => None,
+        scalar => Some(scalar.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn renames(root: &Value, query: &str, replacement: &str) -> Vec<(String, bool)> {
+        let search = Search {
+            query: query.to_string(),
+            mode: SearchMode::Regex,
+            scope: SearchScope::Keys,
+        };
+        search
+            .replacements(root, &JsonPath::new(), replacement)
+            .unwrap()
+            .into_iter()
+            .map(|replacement| (replacement.after, replacement.collides))
+            .collect()
+    }
+
+    #[test]
+    fn renames_onto_the_same_new_key_collide() {
+        let root = json!({"a": 1, "b": 2, "c": 3});
+        assert_eq!(
+            renames(&root, "^(a|b)$", "x"),
+            [(String::from("x"), true), (String::from("x"), true)]
+        );
+    }
+
+    #[test]
+    fn renames_onto_an_existing_key_collide() {
+        let root = json!({"a": 1, "b": 2});
+        assert_eq!(renames(&root, "^a$", "b"), [(String::from("b"), true)]);
+        // swapping names would lose a value too, the renames are made in turn
+        let root = json!({"ab": 1, "ba": 2});
+        assert_eq!(
+            renames(&root, "^(.)(.)$", "$2$1"),
+            [(String::from("ba"), true), (String::from("ab"), true)]
+        );
+    }
+
+    #[test]
+    fn the_same_new_key_in_other_objects_is_fine() {
+        let root = json!({"one": {"a": 1}, "two": {"a": 2, "b": 3}});
+        assert_eq!(
+            renames(&root, "^a$", "z"),
+            [(String::from("z"), false), (String::from("z"), false)]
+        );
+    }
+}
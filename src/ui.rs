@@ -5,14 +5,19 @@ use ratatui::{
     widgets::*,
     Frame,
 };
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::{error::Error, io};
-use std::{fmt::Alignment, rc::Rc, slice::Chunks};
-use std::{fs, ops::Index};
+use std::rc::Rc;
 
-use crate::app::{App, CurrentScreen, CurrentlyDeleting, CurrentlyEditing};
+use crate::app::{
+    App, CurrentScreen, CurrentlyEditing, DiffView, FileOperation, PatchAction, PendingPatch,
+};
+use crate::diff::Change;
+use crate::document::{pointer, preview, to_string_pretty, Document, JsonPath, Row};
+use crate::file_tree::FilePreview;
+use serde_json::Value;
 
-pub fn ui(frame: &mut Frame, app: &App) {
+pub fn ui(frame: &mut Frame, app: &mut App) {
     // Create the layout sections.
     let chunks: Rc<[Rect]> = Layout::default()
         .direction(Direction::Vertical)
@@ -23,7 +28,7 @@ pub fn ui(frame: &mut Frame, app: &App) {
         ])
         .split(frame.area());
 
-    render_title(frame, &chunks);
+    render_title(frame, app, &chunks);
     render_json_values(frame, app, &chunks);
     render_bottombar(frame, app, &chunks);
 
@@ -32,11 +37,44 @@ pub fn ui(frame: &mut Frame, app: &App) {
     }
 
     if let CurrentScreen::Exiting = app.current_screen {
-        render_exit_popup(frame);
+        render_exit_popup(frame, app);
     }
     if let CurrentScreen::Deleting = app.current_screen {
         render_deleting_popup(frame, app);
     }
+    if let CurrentScreen::SavingAs = app.current_screen {
+        render_save_as_popup(frame, app);
+    }
+    if let CurrentScreen::GoingTo = app.current_screen {
+        render_goto_popup(frame, app);
+    }
+    if let CurrentScreen::SavingQuery = app.current_screen {
+        render_save_query_popup(frame, app);
+    }
+    if let CurrentScreen::Replacing = app.current_screen {
+        render_replace_popup(frame, app);
+    }
+    if let CurrentScreen::ConfirmingReplace = app.current_screen {
+        render_replace_preview_popup(frame, app);
+    }
+    if let Some(action) = &app.patch_action {
+        match &app.pending_patch {
+            Some(pending) => render_patch_preview_popup(frame, pending),
+            None => render_patch_popup(frame, app, action),
+        }
+    }
+    if let Some(operation) = &app.file_operation {
+        render_file_operation_popup(frame, app, operation);
+    }
+    if let CurrentScreen::ConfirmingOverwrite = app.current_screen {
+        render_overwrite_popup(frame, app);
+    }
+    if let Some((_, path)) = &app.pending_file_save {
+        render_file_overwrite_popup(frame, path);
+    }
+    if let CurrentScreen::Help = app.current_screen {
+        render_help_popup(frame, app);
+    }
 }
 
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
@@ -62,20 +100,38 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1] // Return the middle chunk
 }
 
-fn render_title(frame: &mut Frame, chunks: &Rc<[Rect]>) {
+fn render_title(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
     let title_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .style(Style::default());
 
-    let title = Paragraph::new(Text::styled(
-        "JSON editor",
-        Style::default().fg(Color::Magenta).bold(),
-    ))
-    .block(title_block)
-    .centered();
+    let file_name = match &app.file_path {
+        Some(path) => path.display().to_string(),
+        None => String::from("[No Name]"),
+    };
+    // `[+]` marks unsaved changes, like vim does
+    let modified = if app.dirty { " [+]" } else { "" };
+    let title_block = title_block
+        .title(Line::from(format!(" JSON editor - {file_name}{modified} ")).centered())
+        .title_style(Style::default().fg(Color::Magenta).bold());
+
+    // one entry per open document, numbered for Alt+number
+    let tab_titles = app
+        .tab_names()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, dirty))| {
+            let modified = if dirty { " [+]" } else { "" };
+            Line::from(format!("{}:{name}{modified}", index + 1))
+        });
+    let tabs = Tabs::new(tab_titles)
+        .block(title_block)
+        .select(app.active_tab)
+        .style(Style::default().fg(Color::Gray))
+        .highlight_style(Style::default().fg(Color::Magenta).bold().reversed());
 
-    frame.render_widget(title, chunks[0]);
+    frame.render_widget(tabs, chunks[0]);
 }
 
 fn render_bottombar(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
@@ -107,13 +163,90 @@ fn render_bottombar(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
                     .fg(Color::Yellow)
                     .add_modifier(Modifier::SLOW_BLINK),
             ),
+            CurrentScreen::FilteringTree => Span::styled(
+                "Filter Tree",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::ManagingFile => Span::styled(
+                "Managing File",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::SavingAs => Span::styled(
+                "Save As",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::ConfirmingOverwrite | CurrentScreen::ConfirmingFileOverwrite => {
+                Span::styled(
+                    "Overwrite?",
+                    Style::default()
+                        .fg(Color::LightRed)
+                        .add_modifier(Modifier::RAPID_BLINK),
+                )
+            }
+            CurrentScreen::GoingTo => Span::styled(
+                "Go To",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::Searching => Span::styled(
+                "Search",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::Help => Span::styled("Help", Style::default().fg(Color::Green)),
+            CurrentScreen::Querying => Span::styled(
+                "Query",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::SavingQuery => Span::styled(
+                "Save Query Result",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::Replacing => Span::styled(
+                "Replace",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::ConfirmingReplace => Span::styled(
+                "Confirm Replace",
+                Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::RAPID_BLINK),
+            ),
+            CurrentScreen::Patching => Span::styled(
+                "Patch",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::SLOW_BLINK),
+            ),
+            CurrentScreen::ConfirmingPatch => Span::styled(
+                "Apply Patch?",
+                Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::RAPID_BLINK),
+            ),
         }
         .to_owned(),
         // A white divider bar to separate the two sections
         Span::styled(" | ", Style::default().fg(Color::White)),
         // The final section of the text, with hints on what the user is editing
         {
-            if let Some(editing) = &app.currently_editing {
+            if let Some(message) = &app.status_message {
+                Span::styled(message.clone(), Style::default().fg(Color::LightRed))
+            } else if let Some(editing) = &app.currently_editing {
                 match editing {
                     CurrentlyEditing::Key => {
                         Span::styled("Editing Json Key", Style::default().fg(Color::Green))
@@ -128,9 +261,22 @@ fn render_bottombar(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
         },
     ];
 
+    // the JSON Pointer of the selection, always shown on the footer's border
+    let selected = app
+        .selected_path()
+        .map(|path| format!(" {} ", pointer(&path)))
+        .unwrap_or_default();
+
+    let match_count = match app.matches.len() {
+        0 => String::new(),
+        count => format!(" match {} of {count} ", app.current_match + 1),
+    };
+
     // Left FOOTER
     let mode_footer = Paragraph::new(Line::from(current_navigation_text)).block(
         Block::default()
+            .title(Line::from(selected).fg(Color::Cyan))
+            .title(Line::from(match_count).fg(Color::Yellow).right_aligned())
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
             .border_type(BorderType::Rounded),
@@ -139,23 +285,89 @@ fn render_bottombar(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
     let current_keys_hint = {
         match app.current_screen {
             CurrentScreen::Main => Span::styled(
-                "(q) or (CTRL+c) to quit / (e) to make new pair / (d) to delete a pair",
+                "(?) all keys / (q) quit / (e) edit / (a) add / (d) delete / (/) search",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Help => Span::styled(
+                "▲ ▼ to scroll / (?) or (ESC) to close",
                 Style::default().fg(Color::Red),
             ),
             CurrentScreen::Editing => Span::styled(
-                "(ESC) to cancel/(Tab) to switch boxes/enter to complete",
+                "(ESC) to cancel/(Tab) to switch boxes/(CTRL+t) json or string value/enter to complete",
                 Style::default().fg(Color::Red),
             ),
             CurrentScreen::Exiting => Span::styled(
-                "(q) to quit / (e) to make new pair",
+                "(s) to save and quit / (d) to quit without saving / (c) or (ESC) to keep editing",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::SavingAs => Span::styled(
+                "(ESC) to cancel / enter to save",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Deleting => Span::styled(
+                "(y) or enter to delete / (n) or (ESC) to cancel",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::ConfirmingOverwrite => Span::styled(
+                "(y) or enter to overwrite / (n) or (ESC) to keep editing",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::ConfirmingFileOverwrite => Span::styled(
+                "(y) or enter to overwrite / (n) or (ESC) to pick another name",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::GoingTo => Span::styled(
+                "(ESC) to cancel / enter to go to the first match, then (n) (N) for the others",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Searching => Span::styled(
+                "type to search / (tab) plain, ignore case or regex / (shift+tab) keys, values or both / enter to keep the matches / (ESC) to cancel",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Querying => Span::styled(
+                "type a jq-like query / ▲ ▼ to select a node / (PgUp) (PgDn) scroll the result / (CTRL+r) replace the document / (CTRL+e) replace the selected node / (CTRL+s) save the result / (ESC) to close",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::SavingQuery => Span::styled(
+                "(ESC) to cancel / enter to save",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Replacing => Span::styled(
+                "(ESC) to cancel / (Tab) to switch boxes / (CTRL+t) plain, ignore case or regex / (CTRL+k) keys, values or both / (CTRL+a) whole document or selection / enter to preview",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::ConfirmingReplace => Span::styled(
+                "▲ ▼ to select / (y) accept / (n) reject / (space) toggle / enter to replace the accepted / (a) replace all / (ESC) to cancel",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Patching => Span::styled(
+                "(ESC) to cancel / enter to confirm",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::ConfirmingPatch => Span::styled(
+                "(y) or enter to apply / (n) or (ESC) to cancel",
                 Style::default().fg(Color::Red),
             ),
-            CurrentScreen::Deleting => {
-                Span::styled("enter to delete", Style::default().fg(Color::Red))
-            }
             CurrentScreen::FileTree => {
-                Span::styled(" ▲ ▼ to scroll", Style::default().fg(Color::Red))
+                Span::styled(
+                    " ▲ ▼ to scroll / ◀ ▶ to fold / enter to open / (r) refresh / (R) make root / (-) parent dir / (a) new file / (m) rename or move / (c) duplicate / (d) delete / (/) filter by name / (.) hidden files / (I) ignored files / (J) json files only / (2) back to json",
+                    Style::default().fg(Color::Red),
+                )
             }
+            CurrentScreen::ManagingFile => match app.file_operation {
+                Some(FileOperation::Delete) => Span::styled(
+                    "(y) or enter to move to the trash / (n) or (ESC) to cancel",
+                    Style::default().fg(Color::Red),
+                ),
+                _ => Span::styled(
+                    "(ESC) to cancel / enter to confirm",
+                    Style::default().fg(Color::Red),
+                ),
+            },
+            CurrentScreen::FilteringTree => Span::styled(
+                "type to filter by name / enter to keep the filter / (ESC) to clear it",
+                Style::default().fg(Color::Red),
+            ),
         }
     };
 
@@ -174,18 +386,25 @@ fn render_bottombar(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
     frame.render_widget(key_notes_footer, footer_chunks[1]);
 }
 
-fn render_exit_popup(frame: &mut Frame) {
+fn render_exit_popup(frame: &mut Frame, app: &App) {
     frame.render_widget(Clear, frame.area()); //this clears the entire screen and anything already drawn
     let popup_block = Block::default()
-        .title("Y/N")
+        .title("Unsaved changes")
         .title_alignment(ratatui::layout::Alignment::Center)
         .add_modifier(Modifier::BOLD)
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .style(Style::default());
 
+    let question = match &app.file_path {
+        Some(path) => format!(
+            "{} has unsaved changes. (s)ave / (d)iscard / (c)ancel",
+            path.display()
+        ),
+        None => String::from("The buffer has unsaved changes. (s)ave as / (d)iscard / (c)ancel"),
+    };
     let exit_text = Text::styled(
-        "Would you like to output the buffer as json? (y/n)",
+        question,
         Style::default()
             .fg(Color::Red)
             .add_modifier(Modifier::SLOW_BLINK),
@@ -201,8 +420,16 @@ fn render_exit_popup(frame: &mut Frame) {
 }
 
 fn render_editing_popup(frame: &mut Frame, app: &App, editing: &CurrentlyEditing) {
+    let parent = match pointer(&app.insertion_parent()) {
+        root if root.is_empty() => String::from("/"),
+        parent => parent,
+    };
+    let title = match &app.editing_path {
+        Some(path) => format!("Edit {}", pointer(path)),
+        None => format!("Enter a new key-value pair in {parent}"),
+    };
     let popup_block = Block::default()
-        .title("Enter a new key-value pair")
+        .title(title)
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .style(Style::default());
@@ -221,7 +448,11 @@ fn render_editing_popup(frame: &mut Frame, app: &App, editing: &CurrentlyEditing
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded);
     let mut value_block = Block::default()
-        .title("Value")
+        .title(if app.value_as_string {
+            "Value (string)"
+        } else {
+            "Value (json)"
+        })
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded);
 
@@ -232,6 +463,15 @@ fn render_editing_popup(frame: &mut Frame, app: &App, editing: &CurrentlyEditing
         CurrentlyEditing::Value => value_block = value_block.style(active_style),
     };
 
+    // an empty box is not an error yet, there is just nothing typed
+    if !app.value_input.is_empty() {
+        if let Err(err) = app.parsed_value() {
+            value_block = value_block
+                .border_style(Style::default().fg(Color::Red))
+                .title_bottom(Line::from(err.to_string()).fg(Color::Red));
+        }
+    }
+
     let key_text = Paragraph::new(app.key_input.clone()).block(key_block);
     frame.render_widget(key_text, popup_chunks[0]);
 
@@ -240,7 +480,7 @@ fn render_editing_popup(frame: &mut Frame, app: &App, editing: &CurrentlyEditing
 }
 fn render_deleting_popup(frame: &mut Frame, app: &App) {
     let popup_block = Block::default()
-        .title("Enter an index to delete")
+        .title("Delete the selected pair? (y/n)")
         .bold()
         .italic()
         .title_alignment(ratatui::layout::Alignment::Center)
@@ -248,80 +488,702 @@ fn render_deleting_popup(frame: &mut Frame, app: &App) {
         .border_type(BorderType::Rounded)
         .style(Style::default());
 
-    let delete_text = Paragraph::new(app.delete_index.clone()).block(popup_block);
+    let selected = app
+        .selected_path()
+        .map(|path| pointer(&path))
+        .unwrap_or_default();
+    let delete_text = Paragraph::new(selected)
+        .block(popup_block)
+        .wrap(Wrap { trim: false });
 
     let area = centered_rect(30, 25, frame.area());
+    frame.render_widget(Clear, area);
     frame.render_widget(delete_text, area);
 }
 
-fn render_json_values(frame: &mut Frame, app: &App, chunks: &Rc<[Rect]>) {
-    let mut list_items = Vec::<ListItem>::new();
-    let mut index = 0;
+fn render_file_overwrite_popup(frame: &mut Frame, path: &Path) {
+    let popup_block = Block::default()
+        .title("Y/N")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
 
-    let mut json_block = Block::new()
-        .title("[2] JSON ")
-        .title_style(
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        )
+    let overwrite_text = Text::styled(
+        format!("{} already exists, overwrite it? (y/n)", path.display()),
+        Style::default().fg(Color::Red),
+    );
+    let overwrite_paragraph = Paragraph::new(overwrite_text)
+        .block(popup_block)
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: false });
+
+    let area = centered_rect(40, 20, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(overwrite_paragraph, area);
+}
+
+fn render_overwrite_popup(frame: &mut Frame, app: &App) {
+    let popup_block = Block::default()
+        .title("Y/N")
+        .title_alignment(ratatui::layout::Alignment::Center)
         .borders(Borders::ALL)
-        .border_type(BorderType::Thick)
+        .border_type(BorderType::Rounded)
         .style(Style::default());
 
-    // if not focused
-    if let (file_tree) = CurrentScreen::FileTree {
-        json_block = Block::new()
-            .title("[2] JSON ")
-            .title_style(
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            )
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded)
-            .style(Style::default());
+    let overwrite_text = Text::styled(
+        format!("\"{}\" already exists, overwrite it? (y/n)", app.key_input),
+        Style::default().fg(Color::Red),
+    );
+    let overwrite_paragraph = Paragraph::new(overwrite_text)
+        .block(popup_block)
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: false });
+
+    let area = centered_rect(40, 20, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(overwrite_paragraph, area);
+}
+
+fn render_file_operation_popup(frame: &mut Frame, app: &App, operation: &FileOperation) {
+    let source = app.file_operation_source.display();
+    let title = match operation {
+        FileOperation::Create => format!("New file in {source}"),
+        FileOperation::Rename => format!("Rename or move {source} to"),
+        FileOperation::Duplicate => format!("Duplicate {source} as"),
+        FileOperation::Delete => String::from("Move to the trash? (y/n)"),
+    };
+    let popup_block = Block::default()
+        .title(title)
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let text = match operation {
+        FileOperation::Delete => source.to_string(),
+        _ => app.file_operation_input.clone(),
+    };
+    let path_text = Paragraph::new(text)
+        .block(popup_block)
+        .wrap(Wrap { trim: false });
+
+    let area = centered_rect(50, 25, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(path_text, area);
+}
+
+fn render_patch_popup(frame: &mut Frame, app: &App, action: &PatchAction) {
+    let title = match action {
+        PatchAction::Export => "Export the session's changes as a JSON Patch to",
+        PatchAction::Apply => "Apply the JSON Patch in",
+        PatchAction::ApplyMerge => "Apply the JSON Merge Patch in",
+        PatchAction::ExportMerge => "Write the merge patch between the documents to",
+    };
+    let popup_block = Block::default()
+        .title(title)
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let path_text = Paragraph::new(app.patch_input.clone()).block(popup_block);
+
+    let area = centered_rect(50, 25, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(path_text, area);
+}
+
+/// Lists what applying the patch would change, colored like the diff view.
+fn render_patch_preview_popup(frame: &mut Frame, pending: &PendingPatch) {
+    let popup_block = Block::default()
+        .title(format!(
+            "Apply {}? {} changes (y/n)",
+            pending.label,
+            pending.differences.len()
+        ))
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let lines: Vec<Line> = pending
+        .differences
+        .iter()
+        .map(|difference| {
+            let (sign, color) = match difference.change {
+                Change::Added => ("+", Color::Green),
+                Change::Removed => ("-", Color::Red),
+                Change::Changed => ("~", Color::Yellow),
+            };
+            Line::from(format!("{sign} {}", pointer(&difference.path))).fg(color)
+        })
+        .collect();
+    let preview_text = Paragraph::new(lines).block(popup_block);
+
+    let area = centered_rect(60, 60, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(preview_text, area);
+}
+
+fn render_replace_popup(frame: &mut Frame, app: &App) {
+    let popup_block = Block::default()
+        .title(format!(
+            "Find and replace ({}) in {}",
+            app.search.describe(),
+            app.replace_scope()
+        ))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let area = centered_rect(60, 25, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup_block, area);
+
+    let popup_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .margin(1)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let mut find_block = Block::default()
+        .title("Find")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded);
+    let mut replace_block = Block::default()
+        .title("Replace with")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded);
+
+    let active_style = Style::default().bg(Color::LightYellow).fg(Color::Black);
+    if app.typing_replacement {
+        replace_block = replace_block.style(active_style);
+    } else {
+        find_block = find_block.style(active_style);
+    }
+    if let Err(err) = app.search.regex() {
+        find_block = find_block
+            .border_style(Style::default().fg(Color::Red))
+            .title_bottom(Line::from(err.to_string()).fg(Color::Red));
     }
 
+    let find_text = Paragraph::new(app.search.query.clone()).block(find_block);
+    frame.render_widget(find_text, popup_chunks[0]);
+
+    let replace_text = Paragraph::new(app.replace_input.clone()).block(replace_block);
+    frame.render_widget(replace_text, popup_chunks[1]);
+}
+
+/// Every change a replace would make, accepted ones marked with a check.
+fn render_replace_preview_popup(frame: &mut Frame, app: &mut App) {
+    let accepted = app
+        .replacements
+        .iter()
+        .filter(|replacement| replacement.accepted)
+        .count();
+    let popup_block = Block::default()
+        .title(format!(
+            "Replace? {accepted} of {} changes accepted",
+            app.replacements.len()
+        ))
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let items: Vec<ListItem> = app
+        .replacements
+        .iter()
+        .map(|replacement| {
+            let (mark, color) = if replacement.collides {
+                ("[!]", Color::Red)
+            } else if replacement.accepted {
+                ("[x]", Color::Green)
+            } else {
+                ("[ ]", Color::Gray)
+            };
+            let what = if replacement.key { "key" } else { "value" };
+            let mut spans = vec![
+                Span::styled(format!("{mark} "), Style::default().fg(color)),
+                Span::raw(format!("{} {what}: ", pointer(&replacement.path))),
+                Span::styled(replacement.before.clone(), Style::default().fg(Color::Red)),
+                Span::raw(" → "),
+                Span::styled(replacement.after.clone(), Style::default().fg(Color::Green)),
+            ];
+            if replacement.collides {
+                spans.push(Span::styled(
+                    "  key taken, skipped",
+                    Style::default().fg(Color::Red),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items).block(popup_block).highlight_style(
+        Style::default()
+            .bg(Color::DarkGray)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let area = centered_rect(70, 60, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut app.replacements_state);
+}
+
+fn render_save_query_popup(frame: &mut Frame, app: &App) {
+    let popup_block = Block::default()
+        .title("Save the query result to")
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let path_text = Paragraph::new(app.query_output_input.clone()).block(popup_block);
+
+    let area = centered_rect(60, 25, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(path_text, area);
+}
+
+/// Every key of the main screen and what it does, for the help popup.
+const MAIN_KEYS: [(&str, &str); 40] = [
+    ("q, CTRL+c", "quit"),
+    ("▲ ▼, j k", "select"),
+    (
+        "PgUp PgDn, Home End",
+        "move a page, to the top or the bottom",
+    ),
+    ("◀ ▶, h l, space", "fold"),
+    ("E, C", "expand or collapse all"),
+    ("z N", "expand to depth N"),
+    ("e, enter", "edit the selected pair"),
+    ("a", "add a pair"),
+    ("d, Delete", "delete the selected pair"),
+    ("s", "sort keys"),
+    ("u, CTRL+z", "undo"),
+    ("CTRL+r, CTRL+y", "redo"),
+    ("CTRL+s", "save"),
+    ("S", "save as"),
+    ("/", "search"),
+    ("n, N", "next or previous match"),
+    ("ESC", "clear matches"),
+    ("R", "find and replace"),
+    ("f", "jq-like query"),
+    (":", "go to a JSON Pointer or JSONPath"),
+    ("y", "copy the selected pointer"),
+    ("g g", "go to the top"),
+    ("g t, g T", "next or previous tab"),
+    ("ALT+N", "switch to tab N"),
+    ("CTRL+n", "new tab"),
+    ("CTRL+w", "close tab"),
+    ("|", "split side by side"),
+    ("-", "split stacked"),
+    ("tab", "other pane"),
+    (">", "copy to the other pane"),
+    ("D", "diff the panes, or with the file on disk"),
+    ("[, ]", "previous or next difference"),
+    ("H, L", "take left or right"),
+    ("p e", "export the changes as a JSON Patch"),
+    ("p a", "apply a JSON Patch"),
+    ("p m", "apply a JSON Merge Patch"),
+    ("p g", "generate a JSON Merge Patch"),
+    ("1", "file tree"),
+    ("2", "back to the json from the file tree"),
+    ("?", "this list"),
+];
+
+fn render_help_popup(frame: &mut Frame, app: &mut App) {
+    let popup_block = Block::default()
+        .title("Keys")
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let lines: Vec<Line> = MAIN_KEYS
+        .iter()
+        .map(|(keys, action)| {
+            Line::from(vec![
+                Span::styled(format!("{keys:>20}  "), Style::default().fg(Color::Yellow)),
+                Span::raw(*action),
+            ])
+        })
+        .collect();
+    let area = centered_rect(60, 80, frame.area());
+    // stop scrolling once the last key is in view, less the two borders
+    let visible = area.height.saturating_sub(2);
+    let last_top = (MAIN_KEYS.len() as u16).saturating_sub(visible);
+    app.help_scroll = app.help_scroll.min(last_top);
+    let help_text = Paragraph::new(lines)
+        .block(popup_block)
+        .scroll((app.help_scroll, 0));
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(help_text, area);
+}
+
+fn render_goto_popup(frame: &mut Frame, app: &App) {
+    let popup_block = Block::default()
+        .title("Go to a JSON Pointer (/servers/0/host) or JSONPath ($.servers[*].host)")
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let goto_text = Paragraph::new(app.goto_input.clone()).block(popup_block);
+
+    let area = centered_rect(60, 25, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(goto_text, area);
+}
+
+fn render_save_as_popup(frame: &mut Frame, app: &App) {
+    let popup_block = Block::default()
+        .title("Save as")
+        .bold()
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+
+    let path_text = Paragraph::new(app.save_as_input.clone()).block(popup_block);
+
+    let area = centered_rect(50, 25, frame.area());
+    frame.render_widget(Clear, area);
+    frame.render_widget(path_text, area);
+}
+
+fn render_json_values(frame: &mut Frame, app: &mut App, chunks: &Rc<[Rect]>) {
     let json_chunks: Rc<[Rect]> = Layout::default()
         .direction(Direction::Horizontal)
         .margin(1)
         .constraints([Constraint::Length(30), Constraint::Min(10)])
         .split(chunks[1]);
 
-    render_file_tree(frame, &json_chunks);
+    render_file_tree(frame, app, &json_chunks);
     // frame.render_widget(&json_block, chunks[1]);
 
-    list_items.push(ListItem::new(Line::from(Span::styled(
-        format!("{: <25} | {: <25} |     {}", "Index", "Key", "Value"),
-        Style::default().fg(Color::LightGreen).bold().italic(),
-    ))));
+    // while browsing the tree, show the highlighted file instead of the open one
+    let tree_focused = matches!(
+        app.current_screen,
+        CurrentScreen::FileTree | CurrentScreen::FilteringTree | CurrentScreen::ManagingFile
+    );
+    if tree_focused {
+        let open = app.file_path.clone();
+        if let Some(preview) = app.file_tree.preview() {
+            if open.as_deref() != Some(preview.path.as_path()) {
+                render_file_preview(frame, preview, json_chunks[1]);
+                return;
+            }
+        }
+    }
 
     //render_json_title(frame, &json_chunks);
 
-    for key in app.pairs.keys() {
-        list_items.push(ListItem::new(Line::from(Span::styled(
-            format!(
-                "{: <25} | {: <25} : {}",
-                index,
-                key,
-                app.pairs.get(key).unwrap()
-            ),
-            Style::default().fg(Color::Yellow),
-        ))));
-        index = index + 1;
+    // a query's result is shown beside the document while it is typed
+    let json_area = match app.current_screen {
+        CurrentScreen::Querying | CurrentScreen::SavingQuery => {
+            let areas = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(json_chunks[1]);
+            render_query_pane(frame, app, areas[1]);
+            areas[0]
+        }
+        _ => json_chunks[1],
+    };
+
+    // the query is shown in the title of the pane being searched
+    let search = match app.current_screen {
+        CurrentScreen::Searching => {
+            format!("/{}_ ({}) ", app.search.query, app.search.describe())
+        }
+        _ => String::new(),
+    };
+    let matched = match_styles(&app.matches);
+
+    let Some(split) = app.split else {
+        let items = json_items(&app.document, &app.expanded, &matched);
+        app.json_page_height = render_json_pane(
+            frame,
+            json_area,
+            format!("[2] JSON {search}"),
+            !tree_focused,
+            items,
+            &mut app.json_list_state,
+            &mut app.vertical_scroll_state,
+        );
+        return;
+    };
+
+    // each pane shows one tab, the focused one is the active tab
+    let pane_areas = Layout::default()
+        .direction(split.direction)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(json_area);
+    let names = app.tab_names();
+    for (side, (&tab, &area)) in split.panes.iter().zip(pane_areas.iter()).enumerate() {
+        let styles = match &app.diff {
+            Some(diff) => diff_styles(diff, side),
+            None => HashMap::new(),
+        };
+        let (name, dirty) = &names[tab];
+        let modified = if *dirty { " [+]" } else { "" };
+        let title = format!("[2] JSON {name}{modified} ");
+        if tab == app.active_tab {
+            let mut styles = styles;
+            styles.extend(matched.clone());
+            let title = format!("{title}{search}");
+            let items = json_items(&app.document, &app.expanded, &styles);
+            app.json_page_height = render_json_pane(
+                frame,
+                area,
+                title,
+                !tree_focused,
+                items,
+                &mut app.json_list_state,
+                &mut app.vertical_scroll_state,
+            );
+        } else {
+            let tab = &mut app.tabs[tab];
+            let items = json_items(&tab.document, &tab.expanded, &styles);
+            render_json_pane(
+                frame,
+                area,
+                title,
+                false,
+                items,
+                &mut tab.json_list_state,
+                &mut tab.vertical_scroll_state,
+            );
+        }
     }
+}
 
-    let list = List::new(list_items).block(json_block);
+/// The read-only result of the query being typed, one pretty printed json
+/// value per output as jq shows them.
+fn render_query_pane(frame: &mut Frame, app: &App, area: Rect) {
+    let mut query_block = Block::new()
+        .title(format!("[3] Query {}_ ", app.query_input))
+        .title_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
 
-    frame.render_widget(list, json_chunks[1]);
+    let lines: Vec<Line> = match &app.query_result {
+        Ok(outputs) => {
+            let count = match outputs.len() {
+                1 => String::from(" 1 result "),
+                count => format!(" {count} results "),
+            };
+            query_block = query_block.title_bottom(count);
+            outputs
+                .iter()
+                .flat_map(|output| {
+                    to_string_pretty(output)
+                        .lines()
+                        .map(|line| Line::from(line.to_string()))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        }
+        Err(message) => {
+            query_block = query_block
+                .border_style(Style::default().fg(Color::Red))
+                .title_bottom(Line::from(message.clone()).fg(Color::Red));
+            Vec::new()
+        }
+    };
+    let result = Paragraph::new(lines)
+        .block(query_block)
+        .style(Style::default().fg(Color::Gray))
+        .scroll((app.query_scroll, 0));
+    frame.render_widget(result, area);
 }
 
-fn render_file_tree(frame: &mut Frame, json_chunks: &Rc<[Rect]>) {
+/// Draws one document list with its scrollbar. Returns how many rows fit.
+fn render_json_pane(
+    frame: &mut Frame,
+    area: Rect,
+    title: String,
+    focused: bool,
+    items: Vec<ListItem>,
+    list_state: &mut ListState,
+    scroll_state: &mut ScrollbarState,
+) -> usize {
+    let json_block = Block::new()
+        .title(title)
+        .title_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .borders(Borders::ALL)
+        .border_type(if focused {
+            BorderType::Thick
+        } else {
+            BorderType::Rounded
+        })
+        .style(Style::default());
+
+    let inner = json_block.inner(area);
+    frame.render_widget(json_block, area);
+
+    let list = List::new(items).highlight_style(
+        Style::default()
+            .bg(Color::DarkGray)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    frame.render_stateful_widget(list, inner, list_state);
+    frame.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight),
+        area,
+        scroll_state,
+    );
+    usize::from(inner.height).max(1)
+}
+
+fn json_items<'a>(
+    document: &Document,
+    expanded: &HashSet<JsonPath>,
+    styles: &HashMap<JsonPath, Style>,
+) -> Vec<ListItem<'a>> {
+    document
+        .rows(&|path| expanded.contains(path))
+        .iter()
+        .map(|row| {
+            let style = styles.get(&row.path).copied().unwrap_or_default();
+            ListItem::new(render_json_row(row, expanded, style))
+        })
+        .collect()
+}
+
+/// Go to and search matches stand out with a yellow background.
+fn match_styles(matches: &[JsonPath]) -> HashMap<JsonPath, Style> {
+    let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+    matches.iter().map(|path| (path.clone(), style)).collect()
+}
+
+/// How the rows of one pane are marked in a diff, `side` 0 being the left pane:
+/// removed paths red, added ones green and changed ones yellow. Objects and
+/// arrays holding a difference are underlined so it can be found when folded.
+fn diff_styles(diff: &DiffView, side: usize) -> HashMap<JsonPath, Style> {
+    let mut styles = HashMap::new();
+    for difference in &diff.differences {
+        let color = match (difference.change, side) {
+            // not on this side
+            (Change::Added, 0) | (Change::Removed, 1) => continue,
+            (Change::Added, _) => Color::Green,
+            (Change::Removed, _) => Color::Red,
+            (Change::Changed, _) => Color::Yellow,
+        };
+        for depth in 1..difference.path.len() {
+            styles
+                .entry(difference.path[..depth].to_vec())
+                .or_insert(Style::default().add_modifier(Modifier::UNDERLINED));
+        }
+        styles.insert(
+            difference.path.clone(),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        );
+    }
+    styles
+}
+
+fn render_file_preview(frame: &mut Frame, preview: &FilePreview, area: Rect) {
+    let name = preview
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let mut preview_block = Block::new()
+        .title(format!("[2] Preview {name} "))
+        .title_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default());
+    if let Some(err) = &preview.error {
+        preview_block = preview_block
+            .border_style(Style::default().fg(Color::Red))
+            .title_bottom(Line::from(err.clone()).fg(Color::Red));
+    }
+
+    let lines: Vec<Line> = preview
+        .lines
+        .iter()
+        .map(|line| Line::from(line.clone()))
+        .collect();
+    let preview_text = Paragraph::new(lines)
+        .block(preview_block)
+        .style(Style::default().fg(Color::Gray));
+    frame.render_widget(preview_text, area);
+}
+
+/// One line of the json tree: indentation for the depth, an expand marker for
+/// objects and arrays, the key and a colored preview of the value.
+fn render_json_row<'a>(row: &Row, expanded: &HashSet<JsonPath>, diff_style: Style) -> Line<'a> {
+    let marker = match row.value {
+        Value::Object(_) | Value::Array(_) if expanded.contains(&row.path) => "▾ ",
+        Value::Object(_) | Value::Array(_) => "▸ ",
+        _ => "  ",
+    };
+    let value_style = match row.value {
+        Value::String(_) => Style::default().fg(Color::LightGreen),
+        Value::Number(_) => Style::default().fg(Color::LightCyan),
+        Value::Bool(_) => Style::default().fg(Color::LightMagenta),
+        Value::Null => Style::default().fg(Color::DarkGray),
+        Value::Object(_) | Value::Array(_) => Style::default().fg(Color::Gray).italic(),
+    };
+
+    Line::from(vec![
+        Span::raw(format!("{}{marker}", "  ".repeat(row.depth))),
+        Span::styled(
+            row.label(),
+            Style::default().fg(Color::Yellow).patch(diff_style),
+        ),
+        Span::styled(" : ", Style::default().fg(Color::White)),
+        Span::styled(preview(row.value), value_style.patch(diff_style)),
+    ])
+}
+
+fn render_file_tree(frame: &mut Frame, app: &mut App, json_chunks: &Rc<[Rect]>) {
     let mut list_items: Vec<ListItem> = Vec::new();
 
+    let filter = &app.file_tree.filter;
+    let mut tree_title = format!("[1] Tree {} ", app.file_tree.root.display());
+    if filter.show_hidden {
+        tree_title.push_str("+hidden ");
+    }
+    if filter.show_ignored {
+        tree_title.push_str("+ignored ");
+    }
+    if filter.json_only {
+        tree_title.push_str("json only ");
+    }
+    if let CurrentScreen::FilteringTree = app.current_screen {
+        tree_title.push_str(&format!("/{}_ ", filter.name));
+    } else if !filter.name.is_empty() {
+        tree_title.push_str(&format!("/{} ", filter.name));
+    }
     let mut tree_block = Block::new()
-        .title("[1] Tree ")
+        .title(tree_title.clone())
         .title_style(
             Style::default()
                 .fg(Color::Yellow)
@@ -332,9 +1194,11 @@ fn render_file_tree(frame: &mut Frame, json_chunks: &Rc<[Rect]>) {
         .style(Style::default());
 
     // if focused
-    if let (file_tree) = CurrentScreen::FileTree {
+    if let CurrentScreen::FileTree | CurrentScreen::FilteringTree | CurrentScreen::ManagingFile =
+        app.current_screen
+    {
         tree_block = Block::new()
-            .title("[1] Tree ")
+            .title(tree_title)
             .title_style(
                 Style::default()
                     .fg(Color::Yellow)
@@ -344,32 +1208,16 @@ fn render_file_tree(frame: &mut Frame, json_chunks: &Rc<[Rect]>) {
             .border_type(BorderType::Thick)
             .style(Style::default());
     }
-    let root_dir = ".";
-    let _ = generate_directory_list(&mut list_items, root_dir, 0);
-    let list = List::new(list_items).block(tree_block);
-    frame.render_widget(list, json_chunks[0]);
-}
-fn generate_directory_list<P: AsRef<Path>>(
-    list_items: &mut Vec<ListItem>,
-    path: P,
-    indent: usize,
-) -> io::Result<()> {
-    let entries = fs::read_dir(&path)?;
-
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-
+    for (indent, node) in app.file_tree.visible() {
         // Add file or directory to the list
-        let name = path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
-
-        let item_text = format!("{}{}", "|- ".repeat(indent), name);
+        let marker = match (node.is_dir, node.expanded) {
+            (true, true) => "▾ ",
+            (true, false) => "▸ ",
+            (false, _) => "  ",
+        };
+        let item_text = format!("{}{marker}{}", "  ".repeat(indent), node.name());
 
-        let style = if path.is_dir() {
+        let style = if node.is_dir {
             Style::default()
                 .fg(Color::LightBlue)
                 .add_modifier(Modifier::BOLD)
@@ -377,15 +1225,21 @@ fn generate_directory_list<P: AsRef<Path>>(
         } else {
             Style::default().fg(Color::White) // Files styled in white
         };
+        // ignored entries are only listed when asked for, and then dimmed
+        let style = if node.ignored {
+            style.add_modifier(Modifier::DIM)
+        } else {
+            style
+        };
 
         list_items.push(ListItem::new(Line::from(Span::styled(item_text, style))));
-
-        // Recursively add directory contents
-        if path.is_dir() {
-            let _ = generate_directory_list(list_items, &path, indent + 1);
-        }
     }
-    Ok(())
+    let list = List::new(list_items)
+        .block(tree_block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    app.file_tree.page_height = usize::from(json_chunks[0].height.saturating_sub(2)).max(1);
+    frame.render_stateful_widget(list, json_chunks[0], &mut app.file_tree.list_state);
 }
 // fn render_json_title(frame: &mut Frame, json_chunks: &Rc<[Rect]>) {
 //     let title_chunks: Rc<[Rect]> = Layout::default()